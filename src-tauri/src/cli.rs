@@ -0,0 +1,613 @@
+use crate::error::LauncherError;
+use crate::events::LauncherEvent;
+use crate::file_manager::{file_exists, get_local_versions};
+use crate::flash_debug::DebugSession;
+use crate::game_logs::{export_log, require_latest_log};
+use crate::integrity::verify_file;
+use crate::launch_options::{parse_pair, LaunchOptions};
+use crate::launcher::Launcher;
+use crate::paths::path_string;
+use crate::preferences::PreferencesStore;
+use crate::settings::Overrides;
+use crate::supervisor::wait_for_exit;
+use crate::version_manager::*;
+use clap::error::{ContextKind, ContextValue, ErrorKind};
+use clap::{CommandFactory, Parser, Subcommand};
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Backyard Monsters Refitted launcher")]
+pub struct Cli {
+    #[command(flatten)]
+    pub overrides: Overrides,
+
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+// Without a command the launcher window opens as usual
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum CliCommand {
+    /// Download the latest version of the selected release channel
+    Update {
+        /// Release channel to update, defaults to the one picked in the launcher
+        #[arg(long)]
+        channel: Option<String>,
+    },
+    /// Start the game without opening the launcher window
+    Launch {
+        /// Build to start, e.g. `stable`
+        #[arg(long)]
+        build: String,
+        /// Game version to start, defaults to the latest downloaded one
+        #[arg(long)]
+        version: Option<String>,
+        /// Runtime to start the game in, by kind (`flash`, `ruffle`) or file
+        /// name. Defaults to the one picked in the launcher.
+        #[arg(long)]
+        runtime: Option<String>,
+        /// Enable Flash trace output and print it while the game runs
+        #[arg(long)]
+        debug: bool,
+        /// Pass a FlashVar to the SWF, can be repeated. Any launch option
+        /// replaces the ones saved in the launcher for this run.
+        #[arg(long = "flashvar", value_name = "NAME=VALUE", value_parser = parse_pair)]
+        flashvars: Vec<(String, String)>,
+        /// Pass an argument to the Flash runtime, can be repeated
+        #[arg(long = "runtime-arg", value_name = "ARG", allow_hyphen_values = true)]
+        runtime_args: Vec<String>,
+        /// Set an environment variable for the game, can be repeated
+        #[arg(long = "env", value_name = "NAME=VALUE", value_parser = parse_pair)]
+        env: Vec<(String, String)>,
+    },
+    /// List the downloaded game versions
+    ListVersions,
+    /// Check the files of the latest downloaded version against the manifest
+    Verify,
+    /// Show where files are kept and what has been downloaded
+    Status,
+    /// Print the path of the latest game log
+    Logs {
+        /// Copy the latest game log to this file
+        #[arg(long)]
+        export: Option<PathBuf>,
+    },
+}
+
+impl Cli {
+    // Shortcuts and the OS can pass arguments of their own, e.g. `-psn_*` on
+    // macOS, so anything that doesn't name a subcommand opens the window
+    // instead of failing, keeping the flags and environment variables that
+    // could be read. Errors are only returned when they should be shown.
+    pub fn parse_or_window<I, T>(args: I) -> Result<Cli, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let mut err = match Cli::try_parse_from(&args) {
+            Ok(cli) => return Ok(cli),
+            Err(err) => err,
+        };
+
+        let command = Cli::command();
+        let names_subcommand = args.iter().skip(1).any(|arg| {
+            arg.to_str()
+                .map_or(false, |arg| command.find_subcommand(arg).is_some())
+        });
+        if names_subcommand
+            || matches!(
+                err.kind(),
+                ErrorKind::DisplayHelp | ErrorKind::DisplayVersion
+            )
+        {
+            return Err(err);
+        }
+
+        eprintln!("Ignoring part of the command line: {}", err.kind());
+        while remove_invalid_arg(&mut args, &err) {
+            match Cli::try_parse_from(&args) {
+                Ok(cli) => return Ok(cli),
+                Err(next) => err = next,
+            }
+        }
+
+        // Only the environment variables are left
+        Ok(Cli::try_parse_from(args.iter().take(1)).unwrap_or(Cli {
+            overrides: Overrides::default(),
+            command: None,
+        }))
+    }
+}
+
+// Drops the argument clap stopped at, returning false if it can't be found
+fn remove_invalid_arg(args: &mut Vec<OsString>, err: &clap::Error) -> bool {
+    let invalid: Vec<&str> = err
+        .context()
+        .filter_map(|context| match context {
+            (
+                ContextKind::InvalidArg
+                | ContextKind::InvalidSubcommand
+                | ContextKind::InvalidValue,
+                ContextValue::String(value),
+            ) => value.split_whitespace().next(),
+            _ => None,
+        })
+        .collect();
+
+    // Unknown flags are reported without their value, e.g. `-p` for `-psn_0_1`
+    let position = args.iter().skip(1).position(|arg| {
+        let arg = arg.to_string_lossy();
+        invalid
+            .iter()
+            .any(|name| arg == *name || (name.starts_with('-') && arg.starts_with(name)))
+    });
+
+    match position {
+        Some(index) => {
+            args.remove(index + 1);
+            true
+        }
+        None => false,
+    }
+}
+
+// Release builds on Windows use the GUI subsystem and start without a console,
+// so the output of a subcommand would go nowhere
+#[cfg(windows)]
+pub fn attach_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+
+    // Fails when started from Explorer, which has no console to attach to
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+pub fn attach_console() {}
+
+// Returns the exit code for the process
+pub fn run(command: CliCommand, launcher: &Launcher, preferences: &PreferencesStore) -> i32 {
+    let result = match command {
+        CliCommand::Update { channel } => update(launcher, preferences, channel).map(|()| 0),
+        CliCommand::Launch {
+            build,
+            version,
+            runtime,
+            debug,
+            flashvars,
+            runtime_args,
+            env,
+        } => {
+            let options = LaunchOptions {
+                flashvars: flashvars.into_iter().collect(),
+                args: runtime_args,
+                env: env.into_iter().collect(),
+            };
+            let options = if options.is_empty() {
+                preferences.get().launch_options(&build)
+            } else {
+                options
+            };
+            launch(
+                launcher,
+                preferences,
+                &build,
+                version,
+                runtime,
+                &options,
+                debug,
+            )
+        }
+        CliCommand::ListVersions => list_versions(launcher).map(|()| 0),
+        CliCommand::Verify => verify(launcher).map(|()| 0),
+        CliCommand::Status => status(launcher, preferences).map(|()| 0),
+        CliCommand::Logs { export } => logs(launcher, export).map(|()| 0),
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            1
+        }
+    }
+}
+
+fn selected_channel(preferences: &PreferencesStore) -> String {
+    preferences
+        .get()
+        .channel
+        .unwrap_or_else(|| DEFAULT_CHANNEL.to_string())
+}
+
+fn update(
+    launcher: &Launcher,
+    preferences: &PreferencesStore,
+    channel: Option<String>,
+) -> Result<(), LauncherError> {
+    let channel = channel.unwrap_or_else(|| selected_channel(preferences));
+    tauri::async_runtime::block_on(launcher.initialize(&channel))
+}
+
+// Returns the exit code to use, so scripts can tell when the game crashed
+fn launch(
+    launcher: &Launcher,
+    preferences: &PreferencesStore,
+    build: &str,
+    version: Option<String>,
+    runtime: Option<String>,
+    options: &LaunchOptions,
+    debug: bool,
+) -> Result<i32, LauncherError> {
+    let local_manifest = get_local_versions(&launcher.paths)?;
+    let runtimes = get_platform_runtimes(
+        env::consts::OS,
+        &VersionManifest::from(local_manifest.clone()),
+    )?;
+    let runtime = match runtime {
+        Some(name) => pick_runtime(&runtimes, &name)?,
+        None => preferences
+            .get()
+            .runtime_for(build, &runtimes)
+            .unwrap_or_else(|| runtimes[0].file.clone()),
+    };
+    let version = version.unwrap_or(local_manifest.current_game_version);
+    let debug_session = if debug {
+        DebugSession::for_runtime(launcher, &runtime)?
+    } else {
+        None
+    };
+
+    let (child, output) =
+        tauri::async_runtime::block_on(launcher.launch(build, &version, &runtime, options, true))?;
+
+    // Waiting keeps the launcher around for as long as the game runs, which
+    // is what Steam and scripts expect
+    let exit = wait_for_exit(child, output, build, &version, &runtime);
+    drop(debug_session);
+    let exit = exit?;
+    preferences.update(|preferences| preferences.record_exit(&exit))?;
+    launcher.emit(LauncherEvent::GameExited(exit.clone()));
+
+    if !exit.crashed {
+        return Ok(0);
+    }
+    Ok(exit.code.filter(|code| *code != 0).unwrap_or(1))
+}
+
+fn pick_runtime(runtimes: &[Runtime], name: &str) -> Result<String, LauncherError> {
+    runtimes
+        .iter()
+        .find(|runtime| runtime.file == name || runtime.kind.name() == name)
+        .map(|runtime| runtime.file.clone())
+        .ok_or_else(|| {
+            let available: Vec<&str> = runtimes
+                .iter()
+                .map(|runtime| runtime.file.as_str())
+                .collect();
+            LauncherError::MissingFile(format!(
+                "a runtime called {}, available are {}",
+                name,
+                available.join(", ")
+            ))
+        })
+}
+
+fn list_versions(launcher: &Launcher) -> Result<(), LauncherError> {
+    let current = get_local_versions(&launcher.paths)
+        .map(|local| local.current_game_version)
+        .unwrap_or_default();
+
+    let installed = installed_versions(&launcher.paths, &current);
+    if installed.is_empty() {
+        println!("No versions have been downloaded yet");
+    }
+    for line in version_lines(&installed) {
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+fn version_lines(installed: &[InstalledVersion]) -> Vec<String> {
+    installed
+        .iter()
+        .map(|installed| {
+            let latest = if installed.current { " (latest)" } else { "" };
+            format!(
+                "{}{}: {}",
+                installed.version,
+                latest,
+                installed.builds.join(", ")
+            )
+        })
+        .collect()
+}
+
+fn verify(launcher: &Launcher) -> Result<(), LauncherError> {
+    let paths = &launcher.paths;
+    let local_manifest = get_local_versions(paths)?;
+    let version = &local_manifest.current_game_version;
+    let checksums = &local_manifest.checksums;
+
+    let mut files: Vec<(String, String)> = local_manifest
+        .builds
+        .iter()
+        .map(|(build_name, build)| {
+            let file_path = path_string(&paths.swf(build_name, build.version_or(version)));
+            (file_path, build.file.clone())
+        })
+        .collect();
+
+    let runtimes = get_platform_runtimes(
+        env::consts::OS,
+        &VersionManifest::from(local_manifest.clone()),
+    )?;
+    for runtime in runtimes {
+        files.push((path_string(&paths.runtime(&runtime.file)), runtime.file));
+    }
+
+    let mut failed = 0;
+    for (file_path, file_name) in &files {
+        let result = if file_exists(file_path) {
+            verify_file(file_path, checksums.get(file_name))
+        } else {
+            Err(LauncherError::MissingFile(file_path.clone()))
+        };
+
+        match result {
+            Ok(()) => println!("ok      {}", file_path),
+            Err(err) => {
+                failed += 1;
+                println!("FAILED  {}: {}", file_path, err);
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(LauncherError::Integrity(format!(
+            "{} of {} files failed verification, run `update` to download them again",
+            failed,
+            files.len()
+        )));
+    }
+
+    println!("All {} files are intact", files.len());
+    Ok(())
+}
+
+fn status(launcher: &Launcher, preferences: &PreferencesStore) -> Result<(), LauncherError> {
+    let paths = &launcher.paths;
+    let portable = if paths.is_portable() {
+        " (portable)"
+    } else {
+        ""
+    };
+
+    println!("Data directory: {}{}", paths.data_dir().display(), portable);
+    println!("Manifest: {}", launcher.settings.manifest_url(true));
+    println!("Selected channel: {}", selected_channel(preferences));
+
+    let local_manifest = match get_local_versions(paths) {
+        Ok(local_manifest) => local_manifest,
+        Err(_) => {
+            println!("Nothing has been downloaded yet, run `update` first");
+            return Ok(());
+        }
+    };
+
+    println!(
+        "Latest downloaded version: {} ({} channel)",
+        local_manifest.current_game_version, local_manifest.channel
+    );
+
+    let builds: Vec<&str> = local_manifest.builds.iter().map(|(name, _)| name).collect();
+    println!("Builds: {}", builds.join(", "));
+
+    let runtimes = get_platform_runtimes(
+        env::consts::OS,
+        &VersionManifest::from(local_manifest.clone()),
+    )?;
+    for runtime in &runtimes {
+        let runtime_state = if paths.runtime(&runtime.file).is_file() {
+            "downloaded"
+        } else {
+            "missing"
+        };
+        println!(
+            "Runtime: {} ({}, {})",
+            runtime.file,
+            runtime.kind.name(),
+            runtime_state
+        );
+    }
+
+    let installed = installed_versions(paths, &local_manifest.current_game_version);
+    println!("Versions on disk: {}", installed.len());
+
+    Ok(())
+}
+
+fn logs(launcher: &Launcher, export: Option<PathBuf>) -> Result<(), LauncherError> {
+    let log = require_latest_log(&launcher.paths.logs())?;
+
+    match export {
+        Some(destination) => {
+            export_log(&log, &destination)?;
+            println!("Copied {} to {}", log.display(), destination.display());
+        }
+        None => println!("{}", log.display()),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_subcommands_with_overrides() {
+        let cli = Cli::parse_from([
+            "bymr-launcher",
+            "launch",
+            "--build",
+            "stable",
+            "--manifest-url",
+            "http://localhost:3001/launcher.json",
+        ]);
+
+        assert_eq!(
+            cli.command,
+            Some(CliCommand::Launch {
+                build: "stable".to_string(),
+                version: None,
+                runtime: None,
+                debug: false,
+                flashvars: Vec::new(),
+                runtime_args: Vec::new(),
+                env: Vec::new(),
+            })
+        );
+        assert_eq!(
+            cli.overrides.manifest_url.as_deref(),
+            Some("http://localhost:3001/launcher.json")
+        );
+    }
+
+    #[test]
+    fn parses_launch_options() {
+        let cli = Cli::parse_from([
+            "bymr-launcher",
+            "launch",
+            "--build",
+            "http",
+            "--flashvar",
+            "server=http://localhost:3001?a=b",
+            "--runtime-arg",
+            "--verbose",
+            "--env",
+            "BYMR_DEBUG=1",
+        ]);
+
+        match cli.command {
+            Some(CliCommand::Launch {
+                flashvars,
+                runtime_args,
+                env,
+                ..
+            }) => {
+                assert_eq!(
+                    flashvars,
+                    vec![(
+                        "server".to_string(),
+                        "http://localhost:3001?a=b".to_string()
+                    )]
+                );
+                assert_eq!(runtime_args, vec!["--verbose"]);
+                assert_eq!(env, vec![("BYMR_DEBUG".to_string(), "1".to_string())]);
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+        assert!(
+            Cli::try_parse_from(["bymr-launcher", "launch", "--build", "http", "--env", "X"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn picks_runtime_by_kind_or_file() {
+        let runtimes = vec![
+            Runtime {
+                file: "flashplayer".to_string(),
+                ..Runtime::default()
+            },
+            Runtime {
+                kind: RuntimeKind::Ruffle,
+                file: "ruffle-nightly".to_string(),
+                ..Runtime::default()
+            },
+        ];
+
+        assert_eq!(pick_runtime(&runtimes, "ruffle").unwrap(), "ruffle-nightly");
+        assert_eq!(
+            pick_runtime(&runtimes, "flashplayer").unwrap(),
+            "flashplayer"
+        );
+        assert!(matches!(
+            pick_runtime(&runtimes, "gnash"),
+            Err(LauncherError::MissingFile(_))
+        ));
+    }
+
+    #[test]
+    fn opens_window_without_subcommand() {
+        let cli = Cli::parse_from(["bymr-launcher", "--keep-previous-versions", "1"]);
+
+        assert_eq!(cli.command, None);
+        assert_eq!(cli.overrides.keep_previous_versions, Some(1));
+        assert!(Cli::try_parse_from(["bymr-launcher", "launch"]).is_err());
+    }
+
+    #[test]
+    fn opens_window_for_unknown_arguments() {
+        let cli = Cli::parse_or_window([
+            "bymr-launcher",
+            "-psn_0_12345",
+            "--keep-previous-versions",
+            "1",
+        ])
+        .unwrap();
+        assert_eq!(cli.command, None);
+        assert_eq!(cli.overrides.keep_previous_versions, Some(1));
+
+        let cli = Cli::parse_or_window([
+            "bymr-launcher",
+            "--bogus=1",
+            "--max-concurrent-downloads",
+            "x",
+            "--keep-previous-versions",
+            "2",
+        ])
+        .unwrap();
+        assert_eq!(cli.overrides.max_concurrent_downloads, None);
+        assert_eq!(cli.overrides.keep_previous_versions, Some(2));
+
+        assert!(Cli::parse_or_window(["bymr-launcher", "launch"]).is_err());
+        assert!(Cli::parse_or_window(["bymr-launcher", "--help"]).is_err());
+        assert!(Cli::parse_or_window(["bymr-launcher", "status"])
+            .unwrap()
+            .command
+            .is_some());
+    }
+
+    #[test]
+    fn lists_versions_with_their_builds() {
+        let installed = vec![
+            InstalledVersion {
+                version: "1.1.0".to_string(),
+                builds: vec!["http".to_string(), "stable".to_string()],
+                current: true,
+            },
+            InstalledVersion {
+                version: "1.0.0".to_string(),
+                builds: vec!["stable".to_string()],
+                current: false,
+            },
+        ];
+
+        assert_eq!(
+            version_lines(&installed),
+            vec!["1.1.0 (latest): http, stable", "1.0.0: stable"]
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::fmt::Display;
+use thiserror::Error;
+
+// Errors are sent to the frontend as `{ code, message }`, so the UI can give
+// specific guidance based on `code`
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum LauncherError {
+    // The request never got a response, e.g. no connection or a timeout
+    #[error("Network error: {0}")]
+    Network(String),
+    #[error("{url} responded with status {status}")]
+    HttpStatus { url: String, status: u16 },
+    #[error("{0}")]
+    Io(String),
+    #[error("{0}")]
+    Decode(String),
+    #[error("Cannot find {0}")]
+    MissingFile(String),
+    #[error("Unsupported platform: {0}")]
+    UnsupportedPlatform(String),
+    #[error("{0}")]
+    Integrity(String),
+    #[error("Unknown release channel: {0}")]
+    UnknownChannel(String),
+    #[error("Invalid launch options: {0}")]
+    InvalidLaunchOptions(String),
+}
+
+impl LauncherError {
+    pub fn io(context: impl Display, err: impl Display) -> Self {
+        LauncherError::Io(format!("{}: {}", context, err))
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            LauncherError::Network(_) => "network",
+            LauncherError::HttpStatus { .. } => "httpStatus",
+            LauncherError::Io(_) => "io",
+            LauncherError::Decode(_) => "decode",
+            LauncherError::MissingFile(_) => "missingFile",
+            LauncherError::UnsupportedPlatform(_) => "unsupportedPlatform",
+            LauncherError::Integrity(_) => "integrity",
+            LauncherError::UnknownChannel(_) => "unknownChannel",
+            LauncherError::InvalidLaunchOptions(_) => "invalidLaunchOptions",
+        }
+    }
+}
+
+impl Serialize for LauncherError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("LauncherError", 2)?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_code_and_message() {
+        let error = LauncherError::HttpStatus {
+            url: "https://api.bymrefitted.com/launcher.json".to_string(),
+            status: 503,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({
+                "code": "httpStatus",
+                "message": "https://api.bymrefitted.com/launcher.json responded with status 503"
+            })
+        );
+    }
+
+    #[test]
+    fn io_errors_keep_their_context() {
+        let error = LauncherError::io("Failed to open version.json", "permission denied");
+        assert_eq!(error.code(), "io");
+        assert_eq!(
+            error.to_string(),
+            "Failed to open version.json: permission denied"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use crate::file_manager::{DownloadProgress, DOWNLOAD_PROGRESS_EVENT};
+use crate::launcher::InitialInfo;
+use crate::supervisor::GameExit;
+use crate::version_manager::{DownloadResult, PruneResult};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+#[derive(Clone, Serialize)]
+struct Payload {
+    message: String,
+}
+
+// Everything the backend tells the frontend while it works
+#[derive(Debug, Clone)]
+pub enum LauncherEvent {
+    Info(String),
+    InitialLoad(Box<InitialInfo>),
+    DownloadProgress(DownloadProgress),
+    DownloadsFinished(Vec<DownloadResult>),
+    VersionsPruned(PruneResult),
+    GameExited(GameExit),
+    // A line the debug Flash Player wrote to flashlog.txt
+    FlashTrace(String),
+}
+
+impl LauncherEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LauncherEvent::Info(_) => "infoLog",
+            LauncherEvent::InitialLoad(_) => "initialLoad",
+            LauncherEvent::DownloadProgress(_) => DOWNLOAD_PROGRESS_EVENT,
+            LauncherEvent::DownloadsFinished(_) => "downloadsFinished",
+            LauncherEvent::VersionsPruned(_) => "versionsPruned",
+            LauncherEvent::GameExited(_) => "gameExited",
+            LauncherEvent::FlashTrace(_) => "flashTrace",
+        }
+    }
+}
+
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: LauncherEvent);
+}
+
+impl EventSink for AppHandle {
+    fn emit(&self, event: LauncherEvent) {
+        let name = event.name();
+        let _ = match event {
+            LauncherEvent::Info(message) => self.emit_all(name, Payload { message }),
+            LauncherEvent::InitialLoad(info) => self.emit_all(name, *info),
+            LauncherEvent::DownloadProgress(progress) => self.emit_all(name, progress),
+            LauncherEvent::DownloadsFinished(results) => self.emit_all(name, results),
+            LauncherEvent::VersionsPruned(pruned) => self.emit_all(name, pruned),
+            LauncherEvent::GameExited(exit) => self.emit_all(name, exit),
+            LauncherEvent::FlashTrace(message) => self.emit_all(name, Payload { message }),
+        };
+    }
+}
+
+// Prints progress for the command line mode
+#[derive(Default)]
+pub struct StdoutEvents {
+    // The last quarter of each download that was reported, so large files
+    // don't flood the output
+    reported: Mutex<HashMap<String, u64>>,
+}
+
+impl EventSink for StdoutEvents {
+    fn emit(&self, event: LauncherEvent) {
+        match event {
+            LauncherEvent::Info(message) => println!("{}", message),
+            LauncherEvent::InitialLoad(info) => println!(
+                "Game version {} ({} channel)",
+                info.manifest.current_game_version, info.manifest.channel
+            ),
+            LauncherEvent::DownloadProgress(progress) if progress.done => {
+                println!(
+                    "Downloaded {} ({:.1} MB)",
+                    progress.file_name,
+                    progress.bytes_received as f64 / 1_000_000.0
+                );
+            }
+            LauncherEvent::DownloadProgress(progress) => {
+                let total = match progress.total_bytes {
+                    Some(total) if total > 0 => total,
+                    _ => return,
+                };
+                let quarter = progress.bytes_received * 4 / total;
+
+                let mut reported = self.reported.lock().unwrap();
+                let last = reported.entry(progress.file_name.clone()).or_insert(0);
+                if quarter > *last && quarter < 4 {
+                    *last = quarter;
+                    println!("Downloading {}: {}%", progress.file_name, quarter * 25);
+                }
+            }
+            LauncherEvent::DownloadsFinished(results) => {
+                println!("{}", downloads_summary(&results))
+            }
+            LauncherEvent::VersionsPruned(pruned) => {
+                if !pruned.removed_versions.is_empty() {
+                    println!(
+                        "Removed old versions {}",
+                        pruned.removed_versions.join(", ")
+                    );
+                }
+            }
+            LauncherEvent::GameExited(exit) if exit.crashed => {
+                println!("The game crashed after {:.0}s", exit.duration_seconds);
+            }
+            LauncherEvent::GameExited(_) => {}
+            LauncherEvent::FlashTrace(line) => println!("[trace] {}", line),
+        }
+    }
+}
+
+// Nothing to download means the update found everything in place
+fn downloads_summary(results: &[DownloadResult]) -> String {
+    let failed = results
+        .iter()
+        .filter(|result| result.error.is_some())
+        .count();
+
+    if results.is_empty() {
+        "Everything is up to date".to_string()
+    } else if failed > 0 {
+        format!("{} of {} downloads failed", failed, results.len())
+    } else {
+        let names: Vec<&str> = results
+            .iter()
+            .map(|result| result.file_name.as_str())
+            .collect();
+        format!("Updated {}", names.join(", "))
+    }
+}
+
+// Keeps every event, so tests can check what the frontend would have seen
+#[cfg(test)]
+#[derive(Default)]
+pub struct RecordedEvents(Mutex<Vec<LauncherEvent>>);
+
+#[cfg(test)]
+impl RecordedEvents {
+    pub fn all(&self) -> Vec<LauncherEvent> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl EventSink for RecordedEvents {
+    fn emit(&self, event: LauncherEvent) {
+        self.0.lock().unwrap().push(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::LauncherError;
+
+    fn result(file_name: &str, error: Option<LauncherError>) -> DownloadResult {
+        DownloadResult {
+            file_name: file_name.to_string(),
+            error,
+        }
+    }
+
+    #[test]
+    fn summarizes_downloads() {
+        assert_eq!(downloads_summary(&[]), "Everything is up to date");
+        assert_eq!(
+            downloads_summary(&[result("bymr-stable.swf", None), result("flashplayer", None)]),
+            "Updated bymr-stable.swf, flashplayer"
+        );
+        assert_eq!(
+            downloads_summary(&[
+                result("bymr-stable.swf", None),
+                result(
+                    "flashplayer",
+                    Some(LauncherError::Network("offline".to_string()))
+                )
+            ]),
+            "1 of 2 downloads failed"
+        );
+    }
+}
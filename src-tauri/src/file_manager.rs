@@ -1,113 +1,723 @@
-use crate::version_manager::{LocalVersionManifest, DOWNLOADS_FOLDER, DOWNLOAD_BASE_PATH};
-use reqwest::Client;
+use crate::error::LauncherError;
+use crate::events::LauncherEvent;
+use crate::integrity::{require_sha256, verify_file, FileIntegrity};
+use crate::launcher::Launcher;
+use crate::paths::LauncherPaths;
+use crate::retry::AttemptError;
+use crate::version_manager::LocalVersionManifest;
+use reqwest::header::{CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Client, Response, StatusCode};
+use serde::Serialize;
 use std::fs;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::path::Path;
-use tokio::fs::File;
+use std::time::{Duration, Instant};
+use tokio::fs::{File, OpenOptions};
 use tokio::io::AsyncWriteExt;
 
-pub async fn download_file(file_path: &str, url: &str, use_https: bool) -> Result<(), String> {
-    let scheme = if use_https { "https" } else { "http" };
-    let full_url = format!("{}://{}{}", scheme, DOWNLOAD_BASE_PATH, url);
-    let client = Client::new();
+pub const DOWNLOAD_PROGRESS_EVENT: &str = "downloadProgress";
 
-    let resp = match client.get(&full_url).send().await {
-        Ok(resp) => resp,
-        Err(err) => return Err(format!("Failed to download file: {}", err)),
-    };
+const TEMP_FILE_SUFFIX: &str = ".tmp";
 
-    if resp.status() != reqwest::StatusCode::OK {
-        return Err(format!(
-            "Could not connect & download file - Status: {} {}",
-            resp.status(),
-            url
-        ));
+// Minimum time between two progress events for the same file
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadProgress {
+    pub file_name: String,
+    pub bytes_received: u64,
+    pub total_bytes: Option<u64>,
+    pub bytes_per_second: f64,
+    pub eta_seconds: Option<f64>,
+    pub done: bool,
+}
+
+impl DownloadProgress {
+    // `resumed_from` bytes were already on disk before this attempt started,
+    // so they count towards the progress but not towards the transfer rate
+    fn new(
+        file_name: &str,
+        resumed_from: u64,
+        session_bytes: u64,
+        total_bytes: Option<u64>,
+        elapsed: Duration,
+        done: bool,
+    ) -> Self {
+        let bytes_received = resumed_from + session_bytes;
+        let elapsed_secs = elapsed.as_secs_f64();
+        let bytes_per_second = if elapsed_secs > 0.0 {
+            session_bytes as f64 / elapsed_secs
+        } else {
+            0.0
+        };
+
+        // ETA is only known when the server sent a Content-Length
+        let eta_seconds = match total_bytes {
+            Some(total) if bytes_per_second > 0.0 => {
+                Some(total.saturating_sub(bytes_received) as f64 / bytes_per_second)
+            }
+            _ => None,
+        };
+
+        DownloadProgress {
+            file_name: file_name.to_string(),
+            bytes_received,
+            total_bytes,
+            bytes_per_second,
+            eta_seconds,
+            done,
+        }
+    }
+}
+
+pub async fn download_file(
+    launcher: &Launcher,
+    file_path: &str,
+    url: &str,
+    use_https: bool,
+    integrity: Option<&FileIntegrity>,
+) -> Result<(), LauncherError> {
+    // Refused before anything is fetched, there would be no way to trust it
+    require_sha256(url, integrity)?;
+
+    let full_url = launcher.settings.download_url(url, use_https);
+    let client = &launcher.client;
+    let policy = &launcher.retry;
+
+    // A file that fails verification is deleted and fetched once more before giving up
+    let mut attempts_left = 2;
+    loop {
+        policy
+            .run(
+                url,
+                || {
+                    fetch_to_file(client, &full_url, file_path, url, |progress| {
+                        launcher.emit(LauncherEvent::DownloadProgress(progress));
+                    })
+                },
+                |notice| launcher.info(notice.to_string()),
+            )
+            .await?;
+
+        let err = match verify_file(file_path, integrity) {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
+
+        let _ = fs::remove_file(file_path);
+        attempts_left -= 1;
+
+        if attempts_left == 0 {
+            return Err(err);
+        }
+        launcher.info(format!("{}, downloading it again", err));
     }
+}
+
+// Checks a file on disk against its manifest entry, replacing it with a
+// fresh download if it has been corrupted or tampered with. Without a
+// checksum the file can't be judged, so it is left alone.
+pub async fn ensure_file_verified(
+    launcher: &Launcher,
+    file_path: &str,
+    url: &str,
+    use_https: bool,
+    integrity: Option<&FileIntegrity>,
+) -> Result<(), LauncherError> {
+    let integrity = Some(require_sha256(url, integrity)?);
+
+    let err = match verify_file(file_path, integrity) {
+        Ok(()) => return Ok(()),
+        Err(err) => err,
+    };
+
+    launcher.info(format!("{}, downloading it again", err));
+    let _ = fs::remove_file(file_path);
+    download_file(launcher, file_path, url, use_https, integrity).await
+}
+
+// Downloads into `<file_path>.part` and only moves the file into place once
+// the whole body has arrived. If a previous attempt left a partial file
+// behind, we ask the server for the remaining bytes instead of starting over.
+async fn fetch_to_file<F>(
+    client: &Client,
+    full_url: &str,
+    file_path: &str,
+    file_name: &str,
+    on_progress: F,
+) -> Result<(), AttemptError>
+where
+    F: FnMut(DownloadProgress),
+{
+    let part_path = format!("{}.part", file_path);
+    let validator_path = format!("{}.validator", part_path);
+
+    let resp = loop {
+        let resume_from = fs::metadata(&part_path).map(|meta| meta.len()).unwrap_or(0);
+        let validator = fs::read_to_string(&validator_path).ok();
+
+        let mut request = client.get(full_url);
+
+        // Only resume when we can tell the server which version of the file
+        // we have, otherwise we could end up stitching two different files together
+        if let (true, Some(validator)) = (resume_from > 0, &validator) {
+            request = request
+                .header(RANGE, format!("bytes={}-", resume_from))
+                .header(IF_RANGE, validator.trim());
+        }
+
+        let resp = match request.send().await {
+            Ok(resp) => resp,
+            Err(err) => {
+                return Err(AttemptError::transient(LauncherError::Network(format!(
+                    "Failed to download {}: {}",
+                    file_name, err
+                ))))
+            }
+        };
+
+        // The partial file no longer lines up with what the server has
+        if resp.status() == StatusCode::RANGE_NOT_SATISFIABLE && resume_from > 0 {
+            remove_partial_download(&part_path, &validator_path);
+            continue;
+        }
+
+        break resp;
+    };
+
+    let resume_from = match resp.status() {
+        StatusCode::PARTIAL_CONTENT => {
+            let resume_from = fs::metadata(&part_path).map(|meta| meta.len()).unwrap_or(0);
+            let range_start = resp
+                .headers()
+                .get(CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_content_range_start);
+
+            if range_start != Some(resume_from) {
+                remove_partial_download(&part_path, &validator_path);
+                return Err(AttemptError::transient(LauncherError::Network(format!(
+                    "Server resumed {} at an unexpected offset",
+                    file_name
+                ))));
+            }
+            resume_from
+        }
+        // Either a fresh download or the server ignored our range request
+        StatusCode::OK => {
+            remove_partial_download(&part_path, &validator_path);
+            if let Some(validator) = resume_validator(&resp) {
+                let _ = fs::write(&validator_path, validator);
+            }
+            0
+        }
+        status => return Err(AttemptError::from_status(status, resp.headers(), full_url)),
+    };
 
-    // Create the file
-    let mut out = match File::create(file_path).await {
+    let mut out = match OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&part_path)
+        .await
+    {
         Ok(file) => file,
-        Err(err) => return Err(format!("Failed to create file: {}", err)),
+        Err(err) => {
+            return Err(AttemptError::fatal(LauncherError::io(
+                format!("Failed to create {}", part_path),
+                err,
+            )))
+        }
     };
 
-    // Write the response body to the file
-    let content = match resp.bytes().await {
-        Ok(bytes) => bytes,
-        Err(err) => return Err(format!("Failed to read response body: {}", err)),
+    // Stream the response body to the file, reporting progress as we go
+    stream_to_file(resp, &mut out, file_name, resume_from, on_progress).await?;
+
+    if let Err(err) = out.sync_all().await {
+        return Err(AttemptError::fatal(LauncherError::io(
+            format!("Failed to write to {}", part_path),
+            err,
+        )));
+    }
+    drop(out);
+
+    if let Err(err) = fs::rename(&part_path, file_path) {
+        return Err(AttemptError::fatal(LauncherError::io(
+            format!("Failed to move {} into place", file_name),
+            err,
+        )));
+    }
+    sync_parent_dir(Path::new(file_path));
+    let _ = fs::remove_file(&validator_path);
+
+    Ok(())
+}
+
+// Only strong ETags may be used with If-Range, otherwise fall back to Last-Modified
+fn resume_validator(resp: &Response) -> Option<String> {
+    let headers = resp.headers();
+    let etag = headers
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"));
+
+    etag.or_else(|| {
+        headers
+            .get(LAST_MODIFIED)
+            .and_then(|value| value.to_str().ok())
+    })
+    .map(str::to_string)
+}
+
+// Parses the start offset out of a `bytes <start>-<end>/<total>` header
+fn parse_content_range_start(value: &str) -> Option<u64> {
+    let range = value.strip_prefix("bytes ")?;
+    let (start, _) = range.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+fn remove_partial_download(part_path: &str, validator_path: &str) {
+    let _ = fs::remove_file(part_path);
+    let _ = fs::remove_file(validator_path);
+}
+
+async fn stream_to_file<F>(
+    mut resp: Response,
+    out: &mut File,
+    file_name: &str,
+    resume_from: u64,
+    mut on_progress: F,
+) -> Result<(), AttemptError>
+where
+    F: FnMut(DownloadProgress),
+{
+    let total_bytes = resp.content_length().map(|len| len + resume_from);
+    let started = Instant::now();
+    let mut last_report: Option<Instant> = None;
+    let mut bytes_received: u64 = 0;
+
+    loop {
+        let chunk = match resp.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(err) => {
+                // Keep what we have so the next attempt can resume from it
+                let _ = out.flush().await;
+                return Err(AttemptError::transient(LauncherError::Network(format!(
+                    "Failed to read response body of {}: {}",
+                    file_name, err
+                ))));
+            }
+        };
+
+        if let Err(err) = out.write_all(&chunk).await {
+            return Err(AttemptError::fatal(LauncherError::io(
+                format!("Failed to write {}", file_name),
+                err,
+            )));
+        }
+
+        bytes_received += chunk.len() as u64;
+
+        let should_report = match last_report {
+            Some(reported_at) => reported_at.elapsed() >= PROGRESS_INTERVAL,
+            None => true,
+        };
+
+        if should_report {
+            on_progress(DownloadProgress::new(
+                file_name,
+                resume_from,
+                bytes_received,
+                total_bytes,
+                started.elapsed(),
+                false,
+            ));
+            last_report = Some(Instant::now());
+        }
+    }
+
+    if let Err(err) = out.flush().await {
+        return Err(AttemptError::fatal(LauncherError::io(
+            format!("Failed to write {}", file_name),
+            err,
+        )));
+    }
+
+    on_progress(DownloadProgress::new(
+        file_name,
+        resume_from,
+        bytes_received,
+        total_bytes,
+        started.elapsed(),
+        true,
+    ));
+
+    Ok(())
+}
+
+// Writes to a temporary file next to `path`, flushes it to disk and then
+// renames it over the destination, so readers only ever see the old or the
+// new contents
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), LauncherError> {
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy(),
+        None => {
+            return Err(LauncherError::Io(format!(
+                "Invalid file path: {}",
+                path.display()
+            )))
+        }
     };
+    let temp_path = path.with_file_name(format!(
+        ".{}.{}{}",
+        file_name,
+        std::process::id(),
+        TEMP_FILE_SUFFIX
+    ));
+
+    let result = write_synced(&temp_path, contents).and_then(|_| fs::rename(&temp_path, path));
 
-    if let Err(err) = out.write_all(&content).await {
-        return Err(format!("Failed to write to file: {}", err));
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(LauncherError::io(
+            format!("Failed to write {}", path.display()),
+            err,
+        ));
     }
 
+    sync_parent_dir(path);
     Ok(())
 }
 
+fn write_synced(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut file = fs::File::create(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+// Makes the rename itself durable. A failure here only means the rename
+// might not survive a power cut, so it is not reported.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    if let Ok(dir) = fs::File::open(parent) {
+        let _ = dir.sync_all();
+    }
+}
+
+// Windows can't open directories for syncing, renames there are durable
+// once the file itself has been flushed
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) {}
+
+// Removes temp files left behind by writes that were interrupted by a crash
+pub fn remove_stale_temp_files(folder: &Path) {
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') && name.ends_with(TEMP_FILE_SUFFIX) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
 pub fn file_exists(file_path: &str) -> bool {
     fs::metadata(file_path).is_ok()
 }
 
-pub fn ensure_folder_exists(folder: &str) -> Result<(), String> {
-    let path = Path::new(folder);
-
-    if !path.exists() {
-        println!("Creating {} folder", folder);
-        if let Err(err) = fs::create_dir(path) {
-            return Err(format!("Failed to create {} folder: {}", folder, err));
+pub fn ensure_folder_exists(folder: &Path) -> Result<(), LauncherError> {
+    if !folder.exists() {
+        println!("Creating {} folder", folder.display());
+        if let Err(err) = fs::create_dir_all(folder) {
+            return Err(LauncherError::io(
+                format!("Failed to create {} folder", folder.display()),
+                err,
+            ));
         }
     }
     Ok(())
 }
 
-pub fn get_local_versions() -> (bool, LocalVersionManifest, String) {
-    let binding = Path::new(DOWNLOADS_FOLDER).join("version.json");
-    let version_file_path = binding.to_str().unwrap();
+pub fn get_local_versions(paths: &LauncherPaths) -> Result<LocalVersionManifest, LauncherError> {
+    let version_file_path = paths.version_file();
 
-    if !file_exists(version_file_path) {
-        return (
-            false,
-            LocalVersionManifest::default(),
-            "File does not exist".to_string(),
-        );
-    } else {
-        match fs::File::open(&version_file_path) {
-            Ok(mut file) => {
-                let mut contents = String::new();
-                if let Err(err) = file.read_to_string(&mut contents) {
-                    return (
-                        false,
-                        LocalVersionManifest::default(),
-                        format!("Failed to read version.json file: {}", err),
-                    );
-                }
-                match serde_json::from_str::<LocalVersionManifest>(&contents) {
-                    Ok(local_manifest) => (true, local_manifest, String::new()),
-                    Err(err) => (
-                        false,
-                        LocalVersionManifest::default(),
-                        format!("Failed to decode version.json file: {}", err),
-                    ),
-                }
-            }
-            Err(err) => (
-                false,
-                LocalVersionManifest::default(),
-                format!("Failed to open version.json file: {}", err),
-            ),
-        }
+    if !version_file_path.exists() {
+        return Err(LauncherError::MissingFile(
+            version_file_path.display().to_string(),
+        ));
     }
+
+    let mut contents = String::new();
+    fs::File::open(&version_file_path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|err| LauncherError::io("Failed to read version.json file", err))?;
+
+    serde_json::from_str(&contents).map_err(|err| {
+        LauncherError::Decode(format!("Failed to decode version.json file: {}", err))
+    })
 }
 
-pub async fn set_local_versions(local_manifest: LocalVersionManifest) -> Result<(), String> {
-    let version_file_path = Path::new(DOWNLOADS_FOLDER).join("version.json");
-    let file = match std::fs::File::create(&version_file_path) {
-        Ok(file) => file,
-        Err(err) => return Err(format!("Failed to create local version manifest: {}", err)),
+pub async fn set_local_versions(
+    paths: &LauncherPaths,
+    local_manifest: LocalVersionManifest,
+) -> Result<(), LauncherError> {
+    let version_file_path = paths.version_file();
+    let contents = match serde_json::to_vec_pretty(&local_manifest) {
+        Ok(contents) => contents,
+        Err(err) => {
+            return Err(LauncherError::Decode(format!(
+                "Failed to encode local version manifest: {}",
+                err
+            )))
+        }
     };
 
-    let mut file_writer = std::io::BufWriter::new(file);
-    match serde_json::to_writer_pretty(&mut file_writer, &local_manifest) {
-        Ok(()) => Ok(()),
-        Err(err) => Err(format!("Failed to encode local version manifest: {}", err)),
+    write_atomic(&version_file_path, &contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::RecordedEvents;
+    use crate::http_client::build_http_client;
+    use crate::retry::RetryPolicy;
+    use crate::settings::Settings;
+    use crate::test_server::{Reply, TestServer};
+    use std::sync::Arc;
+
+    const ETAG_VALUE: &str = "\"runtime-v1\"";
+
+    fn body() -> Vec<u8> {
+        (0..64 * 1024).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn target(dir: &tempfile::TempDir) -> String {
+        dir.path().join("flashplayer").to_str().unwrap().to_string()
+    }
+
+    async fn fetch(server: &TestServer, file_path: &str) -> Result<(), AttemptError> {
+        let client = build_http_client().unwrap();
+        let url = server.url("flashplayer");
+        fetch_to_file(&client, &url, file_path, "flashplayer", |_| {}).await
+    }
+
+    #[tokio::test]
+    async fn resumes_after_dropped_connection() {
+        let full = body();
+        let served = full.clone();
+        let server = TestServer::start(move |req, index| match index {
+            0 => Reply::new(200, served.clone())
+                .header("ETag", ETAG_VALUE)
+                .drop_after(20_000),
+            _ => {
+                assert_eq!(req.header("range"), Some("bytes=20000-"));
+                assert_eq!(req.header("if-range"), Some(ETAG_VALUE));
+                Reply::new(206, served[20_000..].to_vec()).header(
+                    "Content-Range",
+                    format!("bytes 20000-{}/{}", served.len() - 1, served.len()),
+                )
+            }
+        })
+        .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = target(&dir);
+
+        assert!(fetch(&server, &file_path).await.is_err());
+        assert!(!file_exists(&file_path));
+        assert_eq!(
+            fs::metadata(format!("{}.part", file_path)).unwrap().len(),
+            20_000
+        );
+
+        fetch(&server, &file_path).await.unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), full);
+        assert!(!file_exists(&format!("{}.part", file_path)));
+        assert!(!file_exists(&format!("{}.part.validator", file_path)));
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_full_download_when_range_is_ignored() {
+        let full = body();
+        let served = full.clone();
+        let server = TestServer::start(move |_, index| match index {
+            0 => Reply::new(200, served.clone())
+                .header("ETag", ETAG_VALUE)
+                .drop_after(10_000),
+            _ => Reply::new(200, served.clone()).header("ETag", ETAG_VALUE),
+        })
+        .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = target(&dir);
+
+        assert!(fetch(&server, &file_path).await.is_err());
+        fetch(&server, &file_path).await.unwrap();
+
+        assert_eq!(fs::read(&file_path).unwrap(), full);
+        assert!(server.requests()[1].header("range").is_some());
+    }
+
+    #[tokio::test]
+    async fn restarts_without_validator() {
+        let full = body();
+        let served = full.clone();
+        let server = TestServer::start(move |_, index| match index {
+            0 => Reply::new(200, served.clone()).drop_after(10_000),
+            _ => Reply::new(200, served.clone()),
+        })
+        .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = target(&dir);
+
+        assert!(fetch(&server, &file_path).await.is_err());
+        fetch(&server, &file_path).await.unwrap();
+
+        assert_eq!(fs::read(&file_path).unwrap(), full);
+        assert!(server.requests()[1].header("range").is_none());
+    }
+
+    #[tokio::test]
+    async fn restarts_when_range_is_not_satisfiable() {
+        let full = body();
+        let served = full.clone();
+        let server = TestServer::start(move |req, _| match req.header("range") {
+            Some(_) => Reply::new(416, Vec::new()),
+            None => Reply::new(200, served.clone()).header("ETag", ETAG_VALUE),
+        })
+        .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = target(&dir);
+        fs::write(format!("{}.part", file_path), vec![0u8; 128 * 1024]).unwrap();
+        fs::write(format!("{}.part.validator", file_path), ETAG_VALUE).unwrap();
+
+        fetch(&server, &file_path).await.unwrap();
+
+        assert_eq!(fs::read(&file_path).unwrap(), full);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_resume_after_unavailable_server() {
+        let full = body();
+        let served = full.clone();
+        let server = TestServer::start(move |_, index| match index {
+            0 => Reply::new(200, served.clone())
+                .header("ETag", ETAG_VALUE)
+                .drop_after(20_000),
+            1 => Reply::new(503, Vec::new()).header("Retry-After", "0"),
+            _ => Reply::new(206, served[20_000..].to_vec()).header(
+                "Content-Range",
+                format!("bytes 20000-{}/{}", served.len() - 1, served.len()),
+            ),
+        })
+        .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = target(&dir);
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        };
+        let mut retries = 0;
+
+        policy
+            .run(
+                "flashplayer",
+                || fetch(&server, &file_path),
+                |_| retries += 1,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(fs::read(&file_path).unwrap(), full);
+        assert_eq!(retries, 2);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_missing_file() {
+        let server = TestServer::start(|_, _| Reply::new(404, Vec::new())).await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = target(&dir);
+        let err = fetch(&server, &file_path).await.unwrap_err();
+
+        assert!(!err.retryable);
+        assert_eq!(
+            err.error,
+            LauncherError::HttpStatus {
+                url: server.url("flashplayer"),
+                status: 404,
+            }
+        );
+    }
+
+    #[test]
+    fn write_atomic_replaces_file_without_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("version.json");
+        fs::write(&path, b"old").unwrap();
+
+        write_atomic(&path, b"new").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn removes_stale_temp_files_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = dir.path();
+        fs::write(dir.path().join(".version.json.1234.tmp"), b"{").unwrap();
+        fs::write(dir.path().join("version.json"), b"{}").unwrap();
+        fs::write(dir.path().join("flashplayer.part"), b"partial").unwrap();
+
+        remove_stale_temp_files(folder);
+
+        let mut remaining: Vec<_> = fs::read_dir(folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        remaining.sort();
+        assert_eq!(remaining, vec!["flashplayer.part", "version.json"]);
+    }
+
+    #[tokio::test]
+    async fn keeps_file_without_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = target(&dir);
+        fs::write(&path, b"runtime").unwrap();
+        let launcher = Launcher::new(
+            LauncherPaths::new(dir.path()),
+            Settings::default(),
+            build_http_client().unwrap(),
+            Arc::new(RecordedEvents::default()),
+        );
+
+        let result = ensure_file_verified(&launcher, &path, "flashplayer", false, None).await;
+
+        assert!(matches!(result, Err(LauncherError::Integrity(_))));
+        assert_eq!(fs::read(&path).unwrap(), b"runtime");
+    }
+
+    #[test]
+    fn parses_content_range_start() {
+        assert_eq!(parse_content_range_start("bytes 200-999/1000"), Some(200));
+        assert_eq!(parse_content_range_start("bytes */1000"), None);
+        assert_eq!(parse_content_range_start("items 0-1/2"), None);
     }
 }
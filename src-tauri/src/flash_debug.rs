@@ -0,0 +1,544 @@
+use crate::error::LauncherError;
+use crate::events::{EventSink, LauncherEvent};
+use crate::file_manager::{ensure_folder_exists, write_atomic};
+use crate::launcher::Launcher;
+use crate::version_manager::RuntimeKind;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// What the debug Flash Player needs in mm.cfg to write `trace()` output and
+// runtime errors to flashlog.txt
+const TRACE_SETTINGS: [(&str, &str); 2] = [
+    ("TraceOutputFileEnable", "1"),
+    ("ErrorReportingEnable", "1"),
+];
+
+const BACKUP_EXTENSION: &str = "bymr-backup";
+// Left next to an mm.cfg the launcher created, so it is removed again even if
+// the launcher doesn't get to restore it
+const CREATED_EXTENSION: &str = "bymr-created";
+// Holds the id of the launcher process the session belongs to, so another
+// launcher started meanwhile doesn't undo it
+const OWNER_EXTENSION: &str = "bymr-owner";
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// Flash Player looks in the home folder first on every platform
+pub fn mm_cfg_path(home: &Path) -> PathBuf {
+    home.join("mm.cfg")
+}
+
+// Where Flash Player writes the trace log, this can't be changed in mm.cfg
+pub fn flashlog_path(os: &str, home: &Path) -> PathBuf {
+    let logs = match os {
+        "windows" => home.join("AppData/Roaming/Macromedia/Flash Player/Logs"),
+        "macos" => home.join("Library/Preferences/Macromedia/Flash Player/Logs"),
+        _ => home.join(".macromedia/Flash_Player/Logs"),
+    };
+    logs.join("flashlog.txt")
+}
+
+// Turns the trace settings on, leaving anything else the user configured alone
+pub fn patch_mm_cfg(existing: &str) -> String {
+    let newline = if existing.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut missing: Vec<(&str, &str)> = TRACE_SETTINGS.to_vec();
+
+    let mut lines: Vec<String> = existing
+        .lines()
+        .map(|line| {
+            let key = line.split('=').next().unwrap_or_default().trim();
+            match missing
+                .iter()
+                .position(|(name, _)| name.eq_ignore_ascii_case(key))
+            {
+                Some(index) => {
+                    let (name, value) = missing.remove(index);
+                    format!("{}={}", name, value)
+                }
+                None => line.to_string(),
+            }
+        })
+        .collect();
+
+    lines.extend(
+        missing
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value)),
+    );
+
+    let mut patched = lines.join(newline);
+    patched.push_str(newline);
+    patched
+}
+
+// A patched mm.cfg. Until `restore`, the user's own copy is kept next to it,
+// or a marker if the launcher created it.
+pub struct MmCfgPatch {
+    path: PathBuf,
+    backup: PathBuf,
+    created: PathBuf,
+    owner: PathBuf,
+}
+
+impl MmCfgPatch {
+    pub fn new(path: &Path) -> Self {
+        MmCfgPatch {
+            path: path.to_path_buf(),
+            backup: path.with_extension(format!("cfg.{}", BACKUP_EXTENSION)),
+            created: path.with_extension(format!("cfg.{}", CREATED_EXTENSION)),
+            owner: path.with_extension(format!("cfg.{}", OWNER_EXTENSION)),
+        }
+    }
+
+    pub fn apply(path: &Path) -> Result<Self, LauncherError> {
+        let patch = MmCfgPatch::new(path);
+
+        if patch
+            .live_owner()
+            .map_or(false, |owner| owner != process::id())
+        {
+            return Err(LauncherError::Io(
+                "Another launcher is running a debug launch".to_string(),
+            ));
+        }
+        // Cleans up after a session the launcher didn't get to restore
+        patch.restore()?;
+
+        let existing = match fs::read_to_string(path) {
+            Ok(existing) => Some(existing),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => {
+                return Err(LauncherError::io(
+                    format!("Failed to read {}", path.display()),
+                    err,
+                ))
+            }
+        };
+
+        if let Some(folder) = path.parent() {
+            ensure_folder_exists(folder)?;
+        }
+        // Written before mm.cfg is touched, so there is always a way back
+        write_atomic(&patch.owner, process::id().to_string().as_bytes())?;
+        match &existing {
+            Some(existing) => write_atomic(&patch.backup, existing.as_bytes())?,
+            None => write_atomic(&patch.created, b"")?,
+        }
+        let patched = patch_mm_cfg(existing.as_deref().unwrap_or_default());
+        write_atomic(path, patched.as_bytes())?;
+
+        Ok(patch)
+    }
+
+    // Puts back the user's mm.cfg, or removes the one the launcher created.
+    // Does nothing when there is no session to restore.
+    pub fn restore(&self) -> Result<(), LauncherError> {
+        let result = if self.backup.is_file() {
+            println!("Restoring {}", self.path.display());
+            fs::rename(&self.backup, &self.path)
+        } else if self.created.is_file() {
+            println!("Removing {}", self.path.display());
+            remove_if_exists(&self.path).and_then(|()| fs::remove_file(&self.created))
+        } else {
+            Ok(())
+        };
+
+        result
+            .and_then(|()| remove_if_exists(&self.owner))
+            .map_err(|err| {
+                LauncherError::io(format!("Failed to restore {}", self.path.display()), err)
+            })
+    }
+
+    // Restores only when the launcher that patched mm.cfg is gone
+    pub fn restore_abandoned(&self) -> Result<(), LauncherError> {
+        match self.live_owner() {
+            Some(_) => Ok(()),
+            None => self.restore(),
+        }
+    }
+
+    fn live_owner(&self) -> Option<u32> {
+        let owner = fs::read_to_string(&self.owner).ok()?.trim().parse().ok()?;
+        if process_alive(owner) {
+            Some(owner)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    const ESRCH: i32 = 3;
+
+    extern "C" {
+        fn kill(pid: i32, signal: i32) -> i32;
+    }
+
+    // Signal 0 only checks whether the process exists
+    let result = unsafe { kill(pid as i32, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() != Some(ESRCH)
+}
+
+#[cfg(windows)]
+fn process_alive(pid: u32) -> bool {
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    const STILL_ACTIVE: u32 = 259;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> isize;
+        fn GetExitCodeProcess(process: isize, code: *mut u32) -> i32;
+        fn CloseHandle(handle: isize) -> i32;
+    }
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle == 0 {
+            return false;
+        }
+        let mut code = 0;
+        let alive = GetExitCodeProcess(handle, &mut code) != 0 && code == STILL_ACTIVE;
+        CloseHandle(handle);
+        alive
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+// Called when the launcher window opens, in case it was closed during a debug
+// launch
+pub fn restore_mm_cfg() {
+    let home = match tauri::api::path::home_dir() {
+        Some(home) => home,
+        None => return,
+    };
+
+    if let Err(err) = MmCfgPatch::new(&mm_cfg_path(&home)).restore_abandoned() {
+        eprintln!("{}", err);
+    }
+}
+
+// Follows a log file from a background thread, reporting every new line
+pub struct LogTail {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl LogTail {
+    pub fn follow(path: &Path, events: Arc<dyn EventSink>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let path = path.to_path_buf();
+        let stopped = stop.clone();
+
+        let thread = thread::spawn(move || {
+            let mut reader = TailReader::default();
+            loop {
+                // Read once more after stopping, for whatever was written
+                // just before the game exited
+                let last = stopped.load(Ordering::SeqCst);
+                for line in reader.read_new_lines(&path) {
+                    events.emit(LauncherEvent::FlashTrace(line));
+                }
+                if last {
+                    break;
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+
+        LogTail {
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for LogTail {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[derive(Default)]
+struct TailReader {
+    offset: u64,
+    // The end of a line Flash hasn't finished writing yet
+    partial: Vec<u8>,
+}
+
+impl TailReader {
+    fn read_new_lines(&mut self, path: &Path) -> Vec<String> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => return Vec::new(),
+        };
+
+        // Flash Player empties the log when it starts
+        let len = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        if len < self.offset {
+            self.offset = 0;
+            self.partial.clear();
+        }
+
+        let mut read = Vec::new();
+        if file.seek(SeekFrom::Start(self.offset)).is_err() || file.read_to_end(&mut read).is_err()
+        {
+            return Vec::new();
+        }
+        self.offset += read.len() as u64;
+        self.partial.extend_from_slice(&read);
+
+        let complete = match self.partial.iter().rposition(|byte| *byte == b'\n') {
+            Some(end) => self.partial.drain(..=end).collect::<Vec<u8>>(),
+            None => return Vec::new(),
+        };
+
+        String::from_utf8_lossy(&complete)
+            .lines()
+            .map(|line| line.trim_end().to_string())
+            .collect()
+    }
+}
+
+// Enables Flash tracing for one game session. Dropping it stops following
+// flashlog.txt and restores the user's mm.cfg.
+pub struct DebugSession {
+    patch: MmCfgPatch,
+    tail: LogTail,
+}
+
+impl DebugSession {
+    // Ruffle prints traces to its output, which ends up in the game log, so
+    // only Flash Player needs mm.cfg
+    pub fn for_runtime(launcher: &Launcher, runtime: &str) -> Result<Option<Self>, LauncherError> {
+        match launcher.runtime(runtime).kind {
+            RuntimeKind::Flash => DebugSession::start(launcher.events()).map(Some),
+            RuntimeKind::Ruffle => {
+                launcher.info("Debug launch: Ruffle writes trace output to the game log");
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn start(events: Arc<dyn EventSink>) -> Result<Self, LauncherError> {
+        let home = tauri::api::path::home_dir()
+            .ok_or_else(|| LauncherError::MissingFile("the home folder".to_string()))?;
+
+        DebugSession::start_at(
+            &mm_cfg_path(&home),
+            &flashlog_path(env::consts::OS, &home),
+            events,
+        )
+    }
+
+    pub fn start_at(
+        mm_cfg: &Path,
+        flashlog: &Path,
+        events: Arc<dyn EventSink>,
+    ) -> Result<Self, LauncherError> {
+        let patch = MmCfgPatch::apply(mm_cfg)?;
+
+        // Only show traces from this session
+        if let Err(err) = fs::remove_file(flashlog) {
+            if err.kind() != io::ErrorKind::NotFound {
+                eprintln!("Failed to clear {}: {}", flashlog.display(), err);
+            }
+        }
+
+        events.emit(LauncherEvent::Info(format!(
+            "Debug launch, following {}",
+            flashlog.display()
+        )));
+
+        Ok(DebugSession {
+            patch,
+            tail: LogTail::follow(flashlog, events),
+        })
+    }
+}
+
+impl Drop for DebugSession {
+    fn drop(&mut self) {
+        self.tail.stop();
+        if let Err(err) = self.patch.restore() {
+            eprintln!("{}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::RecordedEvents;
+    use std::io::Write;
+
+    fn traces(events: &RecordedEvents) -> Vec<String> {
+        events
+            .all()
+            .into_iter()
+            .filter_map(|event| match event {
+                LauncherEvent::FlashTrace(line) => Some(line),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn patches_only_trace_settings() {
+        let patched = patch_mm_cfg("ErrorReportingEnable=0\nMaxWarnings=50\n");
+
+        assert_eq!(
+            patched,
+            "ErrorReportingEnable=1\nMaxWarnings=50\nTraceOutputFileEnable=1\n"
+        );
+        assert_eq!(
+            patch_mm_cfg(""),
+            "TraceOutputFileEnable=1\nErrorReportingEnable=1\n"
+        );
+        assert_eq!(
+            patch_mm_cfg("traceoutputfileenable = 0\r\n"),
+            "TraceOutputFileEnable=1\r\nErrorReportingEnable=1\r\n"
+        );
+    }
+
+    #[test]
+    fn restores_existing_mm_cfg() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mm.cfg");
+        fs::write(&path, "MaxWarnings=50\n").unwrap();
+
+        let patch = MmCfgPatch::apply(&path).unwrap();
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains("TraceOutputFileEnable=1"));
+
+        patch.restore().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "MaxWarnings=50\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn removes_created_mm_cfg() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Macromedia").join("mm.cfg");
+
+        let patch = MmCfgPatch::apply(&path).unwrap();
+        assert!(path.is_file());
+
+        patch.restore().unwrap();
+        assert!(!path.exists());
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn removes_created_mm_cfg_left_by_earlier_launch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mm.cfg");
+
+        // The launcher was killed before restoring
+        let _ = MmCfgPatch::apply(&path).unwrap();
+
+        MmCfgPatch::new(&path).restore().unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        // Nothing left to restore
+        MmCfgPatch::new(&path).restore().unwrap();
+    }
+
+    #[test]
+    fn recovers_backup_left_by_earlier_launch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mm.cfg");
+        fs::write(&path, "MaxWarnings=50\n").unwrap();
+
+        // The launcher was killed before restoring
+        let _ = MmCfgPatch::apply(&path).unwrap();
+
+        let patch = MmCfgPatch::apply(&path).unwrap();
+        patch.restore().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "MaxWarnings=50\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn leaves_session_of_running_launcher_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mm.cfg");
+        let _ = MmCfgPatch::apply(&path).unwrap();
+
+        // Another launcher owns the session while it runs
+        let mut other = process::Command::new("sleep").arg("10").spawn().unwrap();
+        let patch = MmCfgPatch::new(&path);
+        fs::write(&patch.owner, other.id().to_string()).unwrap();
+
+        patch.restore_abandoned().unwrap();
+        assert!(path.is_file());
+        assert!(MmCfgPatch::apply(&path).is_err());
+
+        other.kill().unwrap();
+        other.wait().unwrap();
+        patch.restore_abandoned().unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn reads_complete_lines_and_follows_truncation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("flashlog.txt");
+        let mut reader = TailReader::default();
+
+        assert!(reader.read_new_lines(&path).is_empty());
+
+        fs::write(&path, "first\nsec").unwrap();
+        assert_eq!(reader.read_new_lines(&path), vec!["first"]);
+
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"ond\r\n").unwrap();
+        assert_eq!(reader.read_new_lines(&path), vec!["second"]);
+
+        fs::write(&path, "again\n").unwrap();
+        assert_eq!(reader.read_new_lines(&path), vec!["again"]);
+    }
+
+    #[test]
+    fn session_streams_traces_and_restores() {
+        let dir = tempfile::tempdir().unwrap();
+        let mm_cfg = dir.path().join("mm.cfg");
+        let flashlog = dir.path().join("Logs").join("flashlog.txt");
+        fs::create_dir_all(flashlog.parent().unwrap()).unwrap();
+        fs::write(&flashlog, "from an earlier session\n").unwrap();
+        let events = Arc::new(RecordedEvents::default());
+
+        let session = DebugSession::start_at(&mm_cfg, &flashlog, events.clone()).unwrap();
+        assert!(mm_cfg.is_file());
+        fs::write(&flashlog, "trace one\ntrace two\n").unwrap();
+        drop(session);
+
+        assert_eq!(traces(&events), vec!["trace one", "trace two"]);
+        assert!(!mm_cfg.exists());
+    }
+}
@@ -0,0 +1,397 @@
+use crate::error::LauncherError;
+use crate::file_manager::write_atomic;
+use chrono::Local;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+pub const LOGS_FOLDER: &str = "logs";
+const LOG_PREFIX: &str = "game-";
+const LOG_SUFFIX: &str = ".log";
+
+// A session that logs more than this continues in a new file
+pub const MAX_LOG_SIZE: u64 = 5 * 1024 * 1024;
+// Older log files are deleted once there are more than this
+pub const MAX_LOG_FILES: usize = 10;
+
+// Writes lines to timestamped log files, starting a new file whenever the
+// current one reaches `max_size` and deleting the oldest beyond `max_files`
+pub struct RotatingLog {
+    folder: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    written: u64,
+}
+
+impl RotatingLog {
+    pub fn create(folder: &Path) -> io::Result<Self> {
+        RotatingLog::with_limits(folder, MAX_LOG_SIZE, MAX_LOG_FILES)
+    }
+
+    pub fn with_limits(folder: &Path, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let file = create_log_file(folder, max_files)?.1;
+
+        Ok(RotatingLog {
+            folder: folder.to_path_buf(),
+            max_size,
+            max_files,
+            file,
+            written: 0,
+        })
+    }
+
+    pub fn write_line(&mut self, stream: &str, line: &str) -> io::Result<()> {
+        if self.written >= self.max_size {
+            self.file = create_log_file(&self.folder, self.max_files)?.1;
+            self.written = 0;
+        }
+
+        let entry = format!(
+            "{} [{}] {}\n",
+            Local::now().format("%H:%M:%S%.3f"),
+            stream,
+            line.trim_end()
+        );
+        self.file.write_all(entry.as_bytes())?;
+        self.written += entry.len() as u64;
+        Ok(())
+    }
+}
+
+// Creates a new log file named after the current time. Files created in the
+// same second get an increasing counter, so they still sort in order.
+fn create_log_file(folder: &Path, max_files: usize) -> io::Result<(PathBuf, File)> {
+    fs::create_dir_all(folder)?;
+    let prefix = format!(
+        "{}{}-",
+        LOG_PREFIX,
+        Local::now().format("%Y-%m-%d_%H-%M-%S")
+    );
+
+    let mut counter = log_files(folder)
+        .iter()
+        .filter_map(|path| {
+            path.file_name()?
+                .to_str()?
+                .strip_prefix(&prefix)?
+                .strip_suffix(LOG_SUFFIX)?
+                .parse::<u32>()
+                .ok()
+        })
+        .max()
+        .map_or(0, |last| last + 1);
+
+    loop {
+        let path = folder.join(format!("{}{:03}{}", prefix, counter, LOG_SUFFIX));
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => {
+                remove_old_logs(folder, max_files);
+                return Ok((path, file));
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => counter += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// Oldest first, the timestamps in the names sort chronologically
+fn log_files(folder: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut logs: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy())
+                .map_or(false, |name| {
+                    name.starts_with(LOG_PREFIX) && name.ends_with(LOG_SUFFIX)
+                })
+        })
+        .collect();
+
+    logs.sort();
+    logs
+}
+
+fn remove_old_logs(folder: &Path, max_files: usize) {
+    let logs = log_files(folder);
+    let excess = logs.len().saturating_sub(max_files.max(1));
+
+    for path in &logs[..excess] {
+        if let Err(err) = fs::remove_file(path) {
+            eprintln!("Failed to remove {}: {}", path.display(), err);
+        }
+    }
+}
+
+pub fn latest_log(folder: &Path) -> Option<PathBuf> {
+    log_files(folder).pop()
+}
+
+pub fn require_latest_log(folder: &Path) -> Result<PathBuf, LauncherError> {
+    latest_log(folder).ok_or_else(|| {
+        LauncherError::MissingFile("a game log, the game has not been launched yet".to_string())
+    })
+}
+
+// Copies a log to `destination`, e.g. to attach it to a bug report
+pub fn export_log(log: &Path, destination: &Path) -> Result<(), LauncherError> {
+    fs::copy(log, destination).map(|_| ()).map_err(|err| {
+        LauncherError::io(
+            format!(
+                "Failed to copy {} to {}",
+                log.display(),
+                destination.display()
+            ),
+            err,
+        )
+    })
+}
+
+// Opens a file with whatever the system uses for it
+pub fn open_path(path: &Path) -> Result<(), LauncherError> {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+
+    command
+        .arg(path)
+        .spawn()
+        .map(|_| ())
+        .map_err(|err| LauncherError::io(format!("Failed to open {}", path.display()), err))
+}
+
+// Cuts a log down to its last `max_size` bytes, where a crash would show up
+fn trim_log(path: &Path, max_size: u64) -> Result<(), LauncherError> {
+    let read_tail = || -> io::Result<Option<Vec<u8>>> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        if len <= max_size {
+            return Ok(None);
+        }
+
+        file.seek(SeekFrom::Start(len - max_size))?;
+        let mut tail = Vec::new();
+        file.take(max_size).read_to_end(&mut tail)?;
+        Ok(Some(tail))
+    };
+
+    match read_tail() {
+        Ok(Some(tail)) => write_atomic(path, &tail),
+        Ok(None) => Ok(()),
+        Err(err) => Err(LauncherError::io(
+            format!("Failed to read {}", path.display()),
+            err,
+        )),
+    }
+}
+
+// The newest log is left alone, a game started earlier may still be writing
+// to it
+fn trim_logs(folder: &Path, max_size: u64) {
+    let mut logs = log_files(folder);
+    logs.pop();
+
+    for path in logs {
+        if let Err(err) = trim_log(&path, max_size) {
+            eprintln!("Failed to trim {}: {}", path.display(), err);
+        }
+    }
+}
+
+// Sends the game's output to a new log file. Piped output goes through a
+// `RotatingLog`, but the launcher has to keep running to read it, otherwise
+// the game would be writing into a closed pipe. Games the launcher doesn't
+// wait for write to the file directly instead, which nothing limits while
+// they run, so earlier logs over `MAX_LOG_SIZE` are trimmed before each new
+// session.
+pub fn redirect_output(
+    command: &mut Command,
+    folder: &Path,
+    piped: bool,
+) -> io::Result<Option<RotatingLog>> {
+    trim_logs(folder, MAX_LOG_SIZE);
+
+    if piped {
+        let log = RotatingLog::create(folder)?;
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        return Ok(Some(log));
+    }
+
+    let (_, file) = create_log_file(folder, MAX_LOG_FILES)?;
+    command.stdout(file.try_clone()?).stderr(file);
+    Ok(None)
+}
+
+// The threads copying a game's output. The last lines, usually the ones
+// explaining a crash, may still be on their way when the game exits.
+#[derive(Default)]
+pub struct OutputCapture(Vec<JoinHandle<()>>);
+
+impl OutputCapture {
+    // Waits until everything the game wrote is in the log
+    pub fn finish(self) {
+        for reader in self.0 {
+            let _ = reader.join();
+        }
+    }
+}
+
+// Copies the child's piped output into the log until the game exits
+pub fn capture_output(child: &mut Child, log: RotatingLog) -> OutputCapture {
+    let log = Arc::new(Mutex::new(log));
+    let mut readers = Vec::new();
+
+    if let Some(stdout) = child.stdout.take() {
+        readers.push(copy_lines(stdout, "stdout", log.clone()));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        readers.push(copy_lines(stderr, "stderr", log));
+    }
+    OutputCapture(readers)
+}
+
+fn copy_lines<R: Read + Send + 'static>(
+    stream: R,
+    name: &'static str,
+    log: Arc<Mutex<RotatingLog>>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut line = Vec::new();
+
+        // Flash doesn't promise UTF-8, so lines are read as bytes
+        while let Ok(read) = reader.read_until(b'\n', &mut line) {
+            if read == 0 {
+                break;
+            }
+
+            let text = String::from_utf8_lossy(&line);
+            if let Err(err) = log.lock().unwrap().write_line(name, &text) {
+                eprintln!("Failed to write game log: {}", err);
+                break;
+            }
+            line.clear();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_logs(folder: &Path) -> Vec<String> {
+        log_files(folder)
+            .iter()
+            .map(|path| fs::read_to_string(path).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn starts_new_file_when_size_is_reached() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = RotatingLog::with_limits(dir.path(), 40, 10).unwrap();
+
+        log.write_line("stdout", "first line of output").unwrap();
+        log.write_line("stderr", "second line\n").unwrap();
+
+        let logs = read_logs(dir.path());
+        assert_eq!(logs.len(), 2);
+        assert!(logs[0].ends_with("[stdout] first line of output\n"));
+        assert!(logs[1].ends_with("[stderr] second line\n"));
+    }
+
+    #[test]
+    fn keeps_only_newest_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = RotatingLog::with_limits(dir.path(), 1, 3).unwrap();
+
+        for index in 0..5 {
+            log.write_line("stdout", &format!("line {}", index))
+                .unwrap();
+        }
+
+        let logs = read_logs(dir.path());
+        assert_eq!(logs.len(), 3);
+        assert!(logs[2].ends_with("line 4\n"));
+        assert!(fs::read_to_string(latest_log(dir.path()).unwrap())
+            .unwrap()
+            .ends_with("line 4\n"));
+    }
+
+    #[test]
+    fn ignores_unrelated_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.txt"), b"").unwrap();
+        assert_eq!(latest_log(dir.path()), None);
+
+        let (path, _) = create_log_file(dir.path(), 10).unwrap();
+        assert_eq!(latest_log(dir.path()), Some(path));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn captures_piped_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("echo hello; echo oops >&2");
+
+        let log = redirect_output(&mut command, dir.path(), true).unwrap();
+        let mut child = command.spawn().unwrap();
+        let output = capture_output(&mut child, log.unwrap());
+        child.wait().unwrap();
+        output.finish();
+
+        let contents = read_logs(dir.path()).concat();
+        assert!(contents.contains("[stdout] hello"));
+        assert!(contents.contains("[stderr] oops"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_directly_to_file_when_not_piped() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("echo hello; echo oops >&2");
+
+        assert!(redirect_output(&mut command, dir.path(), false)
+            .unwrap()
+            .is_none());
+        command.spawn().unwrap().wait().unwrap();
+
+        assert_eq!(read_logs(dir.path()), vec!["hello\noops\n"]);
+    }
+
+    #[test]
+    fn trims_oversized_logs_to_their_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let (long, _) = create_log_file(dir.path(), 10).unwrap();
+        fs::write(&long, b"start\nmiddle\ncrash\n").unwrap();
+        let (short, _) = create_log_file(dir.path(), 10).unwrap();
+        fs::write(&short, b"ok\n").unwrap();
+        let (running, _) = create_log_file(dir.path(), 10).unwrap();
+        fs::write(&running, b"still running\n").unwrap();
+
+        trim_logs(dir.path(), 6);
+
+        assert_eq!(fs::read_to_string(&long).unwrap(), "crash\n");
+        assert_eq!(fs::read_to_string(&short).unwrap(), "ok\n");
+        assert_eq!(fs::read_to_string(&running).unwrap(), "still running\n");
+        assert_eq!(log_files(dir.path()).len(), 3);
+    }
+}
@@ -0,0 +1,70 @@
+use crate::error::LauncherError;
+use reqwest::Client;
+use std::env;
+use std::time::Duration;
+
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Applies between reads rather than to the whole request, so large
+// downloads on slow connections are fine as long as data keeps arriving
+pub const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Small requests such as the manifest should never take longer than this in total
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Fetching the manifest and its signature gives up after this, retries and the
+// http fallback included, so an unreachable server doesn't keep the launcher
+// from starting offline
+pub const MANIFEST_DEADLINE: Duration = Duration::from_secs(20);
+
+pub fn user_agent() -> String {
+    format!(
+        "bymr-launcher/{} ({}; {})",
+        env!("CARGO_PKG_VERSION"),
+        env::consts::OS,
+        env::consts::ARCH
+    )
+}
+
+// Built once at startup and kept in Tauri's managed state, so every request
+// shares the same connection pool
+pub fn build_http_client() -> Result<Client, LauncherError> {
+    Client::builder()
+        .user_agent(user_agent())
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(READ_TIMEOUT)
+        .build()
+        .map_err(|err| LauncherError::Network(format!("Failed to create http client: {}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Reply, TestServer};
+
+    #[test]
+    fn user_agent_identifies_launcher_and_platform() {
+        let user_agent = user_agent();
+        assert!(user_agent.starts_with(&format!("bymr-launcher/{} (", env!("CARGO_PKG_VERSION"))));
+        assert!(user_agent.contains(env::consts::OS));
+        assert!(user_agent.contains(env::consts::ARCH));
+    }
+
+    #[tokio::test]
+    async fn sends_user_agent() {
+        let server = TestServer::start(|_, _| Reply::new(200, "ok")).await;
+        let client = build_http_client().unwrap();
+
+        client
+            .get(server.url("launcher.json"))
+            .send()
+            .await
+            .unwrap();
+
+        let user_agent = user_agent();
+        assert_eq!(
+            server.requests()[0].header("user-agent"),
+            Some(user_agent.as_str())
+        );
+    }
+}
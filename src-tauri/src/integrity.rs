@@ -0,0 +1,184 @@
+use crate::error::LauncherError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileIntegrity {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+pub fn sha256_file(file_path: &str) -> Result<String, LauncherError> {
+    let mut file = match fs::File::open(file_path) {
+        Ok(file) => file,
+        Err(err) => {
+            return Err(LauncherError::io(
+                format!("Failed to open {}", file_path),
+                err,
+            ))
+        }
+    };
+
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) => {
+                return Err(LauncherError::io(
+                    format!("Failed to read {}", file_path),
+                    err,
+                ))
+            }
+        };
+        hasher.update(&buf[..read]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+// Everything that is downloaded or started needs a sha256 from the signed
+// manifest. Without one, a hijacked connection after the http fallback could
+// swap the runtime or a SWF.
+pub fn require_sha256<'a>(
+    file_name: &str,
+    expected: Option<&'a FileIntegrity>,
+) -> Result<&'a FileIntegrity, LauncherError> {
+    match expected {
+        Some(expected) if expected.sha256.is_some() => Ok(expected),
+        _ => Err(LauncherError::Integrity(format!(
+            "The manifest has no sha256 for {}",
+            file_name
+        ))),
+    }
+}
+
+pub fn verify_file(file_path: &str, expected: Option<&FileIntegrity>) -> Result<(), LauncherError> {
+    let expected = require_sha256(file_path, expected)?;
+
+    if let Some(size) = expected.size {
+        let actual = match fs::metadata(file_path) {
+            Ok(meta) => meta.len(),
+            Err(err) => {
+                return Err(LauncherError::io(
+                    format!("Failed to read {}", file_path),
+                    err,
+                ))
+            }
+        };
+
+        if actual != size {
+            return Err(LauncherError::Integrity(format!(
+                "{} is {} bytes, expected {} bytes",
+                file_path, actual, size
+            )));
+        }
+    }
+
+    if let Some(sha256) = &expected.sha256 {
+        let actual = sha256_file(file_path)?;
+        if !actual.eq_ignore_ascii_case(sha256.trim()) {
+            return Err(LauncherError::Integrity(format!(
+                "{} has checksum {}, expected {}",
+                file_path, actual, sha256
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn is_file_valid(file_path: &str, expected: Option<&FileIntegrity>) -> bool {
+    fs::metadata(file_path).is_ok() && verify_file(file_path, expected).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sha256("hello world")
+    const HELLO_SHA256: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+    fn write_hello(dir: &tempfile::TempDir) -> String {
+        let path = dir.path().join("bymr-stable-1.0.0.swf");
+        fs::write(&path, b"hello world").unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn hashes_file_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_hello(&dir);
+        assert_eq!(sha256_file(&path).unwrap(), HELLO_SHA256);
+    }
+
+    #[test]
+    fn accepts_matching_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_hello(&dir);
+        let expected = FileIntegrity {
+            sha256: Some(HELLO_SHA256.to_uppercase()),
+            size: Some(11),
+        };
+
+        assert!(verify_file(&path, Some(&expected)).is_ok());
+    }
+
+    #[test]
+    fn requires_sha256() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_hello(&dir);
+        let size_only = FileIntegrity {
+            sha256: None,
+            size: Some(11),
+        };
+
+        assert_eq!(verify_file(&path, None).unwrap_err().code(), "integrity");
+        assert_eq!(
+            verify_file(&path, Some(&size_only)).unwrap_err().code(),
+            "integrity"
+        );
+        assert!(!is_file_valid(&path, None));
+    }
+
+    #[test]
+    fn rejects_size_or_hash_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_hello(&dir);
+
+        let wrong_size = FileIntegrity {
+            sha256: None,
+            size: Some(12),
+        };
+        let wrong_hash = FileIntegrity {
+            sha256: Some("00".repeat(32)),
+            size: None,
+        };
+
+        assert_eq!(
+            verify_file(&path, Some(&wrong_size)).unwrap_err().code(),
+            "integrity"
+        );
+        assert_eq!(
+            verify_file(&path, Some(&wrong_hash)).unwrap_err().code(),
+            "integrity"
+        );
+        assert!(!is_file_valid(&path, Some(&wrong_hash)));
+    }
+
+    #[test]
+    fn missing_file_is_never_valid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.swf");
+        assert!(!is_file_valid(path.to_str().unwrap(), None));
+    }
+}
@@ -0,0 +1,247 @@
+use crate::error::LauncherError;
+use crate::version_manager::{Runtime, RuntimeKind};
+use indexmap::IndexMap;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::Path;
+use std::process::Command;
+use url::Url;
+
+// Characters left as they are in FlashVars, everything else is escaped the
+// way ActionScript's `unescape` expects
+const FLASHVAR_SAFE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+// How a build is started, saved per build so e.g. a test server only has to
+// be entered once
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LaunchOptions {
+    // Passed to the SWF and read with `loaderInfo.parameters`
+    pub flashvars: IndexMap<String, String>,
+    // Passed to the runtime before the SWF
+    pub args: Vec<String>,
+    pub env: IndexMap<String, String>,
+}
+
+impl LaunchOptions {
+    pub fn is_empty(&self) -> bool {
+        self.flashvars.is_empty() && self.args.is_empty() && self.env.is_empty()
+    }
+
+    pub fn validate(&self) -> Result<(), LauncherError> {
+        if self.flashvars.keys().any(|name| name.trim().is_empty()) {
+            return Err(LauncherError::InvalidLaunchOptions(
+                "FlashVars need a name".to_string(),
+            ));
+        }
+
+        if let Some(name) = self
+            .env
+            .keys()
+            .find(|name| name.is_empty() || name.contains('=') || name.contains('\0'))
+        {
+            return Err(LauncherError::InvalidLaunchOptions(format!(
+                "\"{}\" is not a valid environment variable name",
+                name
+            )));
+        }
+
+        Ok(())
+    }
+
+    // The runtime takes FlashVars as the query of the SWF's URL, so with any
+    // set the SWF is passed as a `file://` URL instead of a path
+    pub fn swf_argument(&self, swf_path: &Path) -> Result<String, LauncherError> {
+        if self.flashvars.is_empty() {
+            return Ok(swf_path.to_string_lossy().into_owned());
+        }
+
+        let absolute = if swf_path.is_absolute() {
+            swf_path.to_path_buf()
+        } else {
+            env::current_dir()
+                .map_err(|err| LauncherError::io("Failed to get the working directory", err))?
+                .join(swf_path)
+        };
+
+        let mut url = Url::from_file_path(&absolute).map_err(|()| {
+            LauncherError::InvalidLaunchOptions(format!(
+                "Cannot pass FlashVars to {}",
+                absolute.display()
+            ))
+        })?;
+        url.set_query(Some(&encode_flashvars(&self.flashvars)));
+
+        Ok(url.to_string())
+    }
+
+    // Ruffle takes FlashVars as `-P name=value` instead and opens the plain path
+    pub fn apply(
+        &self,
+        command: &mut Command,
+        runtime: &Runtime,
+        swf_path: &Path,
+    ) -> Result<(), LauncherError> {
+        command.args(&runtime.args).args(&self.args);
+
+        match runtime.kind {
+            RuntimeKind::Flash => command.arg(self.swf_argument(swf_path)?),
+            RuntimeKind::Ruffle => command
+                .args(
+                    self.flashvars
+                        .iter()
+                        .map(|(name, value)| format!("-P{}={}", name, value)),
+                )
+                .arg(swf_path),
+        };
+
+        command.envs(&self.env);
+        Ok(())
+    }
+}
+
+pub fn encode_flashvars(flashvars: &IndexMap<String, String>) -> String {
+    flashvars
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(name, FLASHVAR_SAFE),
+                utf8_percent_encode(value, FLASHVAR_SAFE)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+// Parses `name=value` from the command line
+pub fn parse_pair(pair: &str) -> Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected name=value, got \"{}\"", pair)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(flashvars: &[(&str, &str)]) -> LaunchOptions {
+        LaunchOptions {
+            flashvars: flashvars
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            ..LaunchOptions::default()
+        }
+    }
+
+    #[test]
+    fn passes_plain_path_without_flashvars() {
+        let path = Path::new("builds/stable/bymr-stable.swf");
+        assert_eq!(
+            LaunchOptions::default().swf_argument(path).unwrap(),
+            path.to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn encodes_flashvars_in_order() {
+        let encoded = encode_flashvars(
+            &options(&[
+                ("server", "http://localhost:3001/api?x=1&y=2"),
+                ("debug", "1"),
+                ("name", "Monster Maker ü"),
+            ])
+            .flashvars,
+        );
+
+        assert_eq!(
+            encoded,
+            "server=http%3A%2F%2Flocalhost%3A3001%2Fapi%3Fx%3D1%26y%3D2&debug=1&name=Monster%20Maker%20%C3%BC"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn passes_swf_as_url_with_flashvars() {
+        let options = options(&[("token", "a b")]);
+
+        assert_eq!(
+            options
+                .swf_argument(Path::new("/data/My Builds/bymr-stable.swf"))
+                .unwrap(),
+            "file:///data/My%20Builds/bymr-stable.swf?token=a%20b"
+        );
+    }
+
+    #[test]
+    fn passes_flashvars_to_ruffle_as_parameters() {
+        let mut options = options(&[("server", "http://localhost:3001"), ("debug", "1")]);
+        options.args.push("--fullscreen".to_string());
+        let runtime = Runtime {
+            kind: RuntimeKind::Ruffle,
+            file: "ruffle".to_string(),
+            args: vec!["--no-gui".to_string()],
+            display_name: None,
+        };
+
+        let mut command = Command::new("ruffle");
+        options
+            .apply(&mut command, &runtime, Path::new("bymr-stable.swf"))
+            .unwrap();
+
+        let args: Vec<_> = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy())
+            .collect();
+        assert_eq!(
+            args,
+            vec![
+                "--no-gui",
+                "--fullscreen",
+                "-Pserver=http://localhost:3001",
+                "-Pdebug=1",
+                "bymr-stable.swf"
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        assert!(options(&[("", "1")]).validate().is_err());
+
+        let mut options = LaunchOptions::default();
+        options.env.insert("A=B".to_string(), "1".to_string());
+        assert!(matches!(
+            options.validate(),
+            Err(LauncherError::InvalidLaunchOptions(_))
+        ));
+
+        options.env.clear();
+        options
+            .env
+            .insert("BYMR_DEBUG".to_string(), "1".to_string());
+        options.args.push("--verbose".to_string());
+        assert!(options.validate().is_ok());
+    }
+
+    #[test]
+    fn parses_pairs() {
+        assert_eq!(
+            parse_pair("server=http://a?b=c"),
+            Ok(("server".to_string(), "http://a?b=c".to_string()))
+        );
+        assert_eq!(
+            parse_pair("empty="),
+            Ok(("empty".to_string(), String::new()))
+        );
+        assert!(parse_pair("novalue").is_err());
+        assert!(parse_pair("=value").is_err());
+    }
+}
@@ -0,0 +1,573 @@
+use crate::error::LauncherError;
+use crate::events::{EventSink, LauncherEvent};
+use crate::file_manager::{ensure_file_verified, file_exists, get_local_versions};
+use crate::game_logs::{capture_output, redirect_output, OutputCapture};
+use crate::integrity::is_file_valid;
+use crate::launch_options::LaunchOptions;
+use crate::paths::{path_string, LauncherPaths};
+use crate::retry::RetryPolicy;
+use crate::settings::Settings;
+use crate::updater::{execute, Updater};
+use crate::version_manager::*;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::Path;
+use std::process::{Child, Command};
+use std::sync::Arc;
+use tauri::{AppHandle, Manager};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InitialInfo {
+    pub platform: String,
+    pub architecture: String,
+    pub manifest: VersionManifest,
+    // Set when the manifest comes from the local cache because the server was unreachable
+    pub offline: bool,
+}
+
+// Everything the update code needs, so it can run against any server and
+// report to something other than the frontend
+#[derive(Clone)]
+pub struct Launcher {
+    pub paths: LauncherPaths,
+    pub settings: Settings,
+    pub client: Client,
+    pub retry: RetryPolicy,
+    events: Arc<dyn EventSink>,
+}
+
+impl Launcher {
+    pub fn new(
+        paths: LauncherPaths,
+        settings: Settings,
+        client: Client,
+        events: Arc<dyn EventSink>,
+    ) -> Self {
+        Launcher {
+            paths,
+            settings,
+            client,
+            retry: RetryPolicy::default(),
+            events,
+        }
+    }
+
+    // Uses the state managed in `main` and reports to the frontend
+    pub fn from_app(app: &AppHandle) -> Self {
+        Launcher::new(
+            app.state::<LauncherPaths>().inner().clone(),
+            app.state::<Settings>().inner().clone(),
+            app.state::<Client>().inner().clone(),
+            Arc::new(app.clone()),
+        )
+    }
+
+    pub fn info(&self, message: impl Into<String>) {
+        self.events.emit(LauncherEvent::Info(message.into()));
+    }
+
+    pub fn emit(&self, event: LauncherEvent) {
+        self.events.emit(event);
+    }
+
+    // The downloaded runtime with the given file name
+    pub fn runtime(&self, file: &str) -> Runtime {
+        let runtimes = get_local_versions(&self.paths)
+            .ok()
+            .and_then(|local| {
+                get_platform_runtimes(env::consts::OS, &VersionManifest::from(local)).ok()
+            })
+            .unwrap_or_default();
+
+        find_runtime(&runtimes, file)
+    }
+
+    // For work that keeps reporting after the launcher is gone
+    pub fn events(&self) -> Arc<dyn EventSink> {
+        self.events.clone()
+    }
+
+    // Brings the selected channel up to date with the server, falling back to
+    // whatever was downloaded last time when the server can't be reached
+    pub async fn initialize(&self, channel: &str) -> Result<(), LauncherError> {
+        let paths = &self.paths;
+
+        // Get OS info
+        self.info(format!(
+            "Platform: {} {}",
+            env::consts::OS,
+            env::consts::ARCH
+        ));
+
+        if paths.is_portable() {
+            self.info(format!(
+                "Portable mode: keeping files in {}",
+                paths.data_dir().display()
+            ));
+        }
+
+        let mut server_manifest = match get_version_info(self).await {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                self.info(format!("Server manifest could not be retrieved. Please check your internet connection. {}", err));
+
+                // Still let people play whatever was downloaded last time
+                return match self.start_offline() {
+                    Ok(()) => Ok(()),
+                    Err(offline_err) => {
+                        self.info(offline_err.to_string());
+                        Err(err)
+                    }
+                };
+            }
+        };
+
+        if let Err(err) = server_manifest.select_channel(channel) {
+            self.info(format!("{}, using {} instead", err, DEFAULT_CHANNEL));
+            let _ = server_manifest.select_channel(DEFAULT_CHANNEL);
+        }
+
+        self.emit(LauncherEvent::InitialLoad(Box::new(InitialInfo {
+            platform: env::consts::OS.to_string(),
+            architecture: env::consts::ARCH.to_string(),
+            manifest: server_manifest.clone(),
+            offline: false,
+        })));
+
+        let local_manifest = local_files_status(paths).ok();
+        let installed = installed_versions(paths, &server_manifest.current_game_version);
+
+        let updater = Updater {
+            paths,
+            server: &server_manifest,
+            local: local_manifest.as_ref(),
+            installed: &installed,
+            keep_previous: self.settings.keep_previous_versions,
+        };
+
+        let plan = match updater.plan(env::consts::OS, is_file_valid) {
+            Ok(plan) => plan,
+            Err(err) => {
+                self.info(format!("Could not download latest flash runtime {}", err));
+                return Err(err);
+            }
+        };
+
+        execute(self, plan).await
+    }
+
+    // Starts the given build, checking the current version's files against
+    // the manifest first. `supervised` games are waited for, so their output
+    // can be piped into rotating logs.
+    pub async fn launch(
+        &self,
+        build_name: &str,
+        version: &str,
+        runtime: &str,
+        options: &LaunchOptions,
+        supervised: bool,
+    ) -> Result<(Child, OutputCapture), LauncherError> {
+        options.validate()?;
+
+        let paths = &self.paths;
+        let binding = paths.runtime(runtime);
+        let flash_runtime_path = binding.to_str().unwrap();
+
+        if !file_exists(flash_runtime_path) {
+            eprintln!("cannot find file: {}", flash_runtime_path);
+            return Err(LauncherError::MissingFile(format!(
+                "flashplayer: {}",
+                flash_runtime_path
+            )));
+        }
+
+        let local_manifest = get_local_versions(paths).unwrap_or_default();
+        let build = local_manifest.builds.get(build_name);
+
+        // The latest version of a build that the manifest pins to its own version
+        // is that pinned version
+        let is_current = version == local_manifest.current_game_version;
+        let version = match build {
+            Some(build) if is_current => build.version_or(version),
+            _ => version,
+        };
+
+        let binding = paths.swf(build_name, version);
+
+        let mut swf_path = String::from(binding.to_str().unwrap());
+
+        if !file_exists(&swf_path) {
+            eprintln!("Cannot find file: {:?}", swf_path);
+            return Err(LauncherError::MissingFile(format!(
+                "swf build: {}",
+                swf_path
+            )));
+        }
+
+        // Verify the files against the checksums we saved during initialization.
+        // Older SWFs were verified when they were downloaded, but are not
+        // checked again: the saved checksums only describe the current
+        // version, and a damaged old SWF couldn't be downloaded again anyway.
+        let checksums = &local_manifest.checksums;
+        let use_https = local_manifest.https_worked;
+
+        ensure_file_verified(
+            self,
+            flash_runtime_path,
+            runtime,
+            use_https,
+            checksums.get(runtime),
+        )
+        .await?;
+
+        if is_current {
+            if let Some(build) = build {
+                ensure_file_verified(
+                    self,
+                    &swf_path,
+                    &build.file,
+                    use_https,
+                    checksums.get(&build.file),
+                )
+                .await?;
+            }
+        }
+
+        // Linux
+        // Set the absolute path to SWF
+        // If error, set permissions to be executable for Flash runtime
+        if env::consts::OS == "linux" {
+            if let Ok(absolute_path) = std::fs::canonicalize(&swf_path) {
+                swf_path = absolute_path.to_str().unwrap().to_owned();
+
+                if let Err(perm_err) = Command::new("chmod")
+                    .arg("+x")
+                    .arg(flash_runtime_path)
+                    .output()
+                {
+                    println!("Linux fix: could not run command: {:?}", perm_err);
+                }
+            }
+        }
+        println!("Opening: {:?}, {:?}", flash_runtime_path, swf_path);
+
+        // Open the game in the chosen runtime
+        let mut command = Command::new(flash_runtime_path);
+        options.apply(&mut command, &self.runtime(runtime), Path::new(&swf_path))?;
+
+        // Missing logs shouldn't keep anyone from playing
+        let log = redirect_output(&mut command, &paths.logs(), supervised).unwrap_or_else(|err| {
+            eprintln!("Could not create game log: {}", err);
+            None
+        });
+
+        let mut child = command.spawn().map_err(|err| {
+            LauncherError::io(
+                format!("[BYMR LAUNCHER] Failed to start BYMR build {}", build_name),
+                err,
+            )
+        })?;
+
+        let output = match log {
+            Some(log) => capture_output(&mut child, log),
+            None => OutputCapture::default(),
+        };
+        Ok((child, output))
+    }
+
+    fn start_offline(&self) -> Result<(), LauncherError> {
+        let local_manifest = local_files_status(&self.paths).map_err(|_| {
+            LauncherError::MissingFile("a downloaded version to play offline".to_string())
+        })?;
+
+        let manifest = VersionManifest::from(local_manifest);
+
+        let flash_runtime_file_name = get_platform_flash_runtime(env::consts::OS, &manifest)?;
+        let flash_runtime_path = path_string(&self.paths.runtime(&flash_runtime_file_name));
+
+        if !is_file_valid(
+            &flash_runtime_path,
+            manifest.checksums.get(&flash_runtime_file_name),
+        ) {
+            return Err(LauncherError::MissingFile(format!(
+                "flash runtime {}, it has not been downloaded",
+                flash_runtime_file_name
+            )));
+        }
+
+        self.info(format!(
+            "Offline: launching the last downloaded version {}",
+            manifest.current_game_version
+        ));
+
+        self.emit(LauncherEvent::InitialLoad(Box::new(InitialInfo {
+            platform: env::consts::OS.to_string(),
+            architecture: env::consts::ARCH.to_string(),
+            manifest,
+            offline: true,
+        })));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::RecordedEvents;
+    use crate::file_manager::get_local_versions;
+    use crate::http_client::build_http_client;
+    use crate::signature::testing;
+    use crate::test_server::{Reply, TestServer};
+    use sha2::{Digest, Sha256};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use std::time::Duration;
+
+    const RUNTIME: &str = "flashplayer";
+
+    fn sha256(contents: &str) -> serde_json::Value {
+        let hash: String = Sha256::digest(contents.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        serde_json::json!({ "sha256": hash })
+    }
+
+    fn manifest(version: &str) -> String {
+        serde_json::json!({
+            "currentGameVersion": version,
+            "currentLauncherVersion": "0.1.2",
+            "builds": { "stable": "bymr-stable.swf", "http": "bymr-http.swf" },
+            "flashRuntimes": { "windows": RUNTIME, "darwin": RUNTIME, "linux": RUNTIME },
+            "checksums": {
+                "bymr-stable.swf": sha256(&format!("stable {}", version)),
+                "bymr-http.swf": sha256(&format!("http {}", version)),
+                RUNTIME: sha256("runtime")
+            },
+            "httpsWorked": false
+        })
+        .to_string()
+    }
+
+    // Everything the server hosts for a release, keyed by request path
+    fn release(version: &str) -> HashMap<String, Reply> {
+        let manifest = manifest(version);
+        let signature = testing::sign(manifest.as_bytes());
+
+        HashMap::from([
+            ("/launcher.json".to_string(), Reply::new(200, manifest)),
+            ("/launcher.json.sig".to_string(), Reply::new(200, signature)),
+            (
+                "/downloads/bymr-stable.swf".to_string(),
+                Reply::new(200, format!("stable {}", version)),
+            ),
+            (
+                "/downloads/bymr-http.swf".to_string(),
+                Reply::new(200, format!("http {}", version)),
+            ),
+            (
+                format!("/downloads/{}", RUNTIME),
+                Reply::new(200, "runtime"),
+            ),
+        ])
+    }
+
+    async fn serve(files: HashMap<String, Reply>) -> TestServer {
+        TestServer::start(move |req, _| {
+            files
+                .get(&req.path)
+                .cloned()
+                .unwrap_or_else(|| Reply::new(404, "not found"))
+        })
+        .await
+    }
+
+    async fn failing_server(status: u16) -> TestServer {
+        TestServer::start(move |_, _| Reply::new(status, "")).await
+    }
+
+    fn launcher(server: &TestServer, data_dir: &Path, events: Arc<RecordedEvents>) -> Launcher {
+        let settings = Settings {
+            manifest_url: server.url("launcher.json"),
+            download_url: server.url("downloads"),
+            keep_previous_versions: 0,
+            manifest_public_key: testing::public_key(),
+            ..Settings::default()
+        };
+
+        let mut launcher = Launcher::new(
+            LauncherPaths::new(data_dir),
+            settings,
+            build_http_client().unwrap(),
+            events,
+        );
+        launcher.retry = RetryPolicy {
+            max_attempts: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+            jitter: 0.0,
+        };
+        launcher
+    }
+
+    fn requests_for(server: &TestServer, path: &str) -> usize {
+        server
+            .requests()
+            .iter()
+            .filter(|req| req.path == path)
+            .count()
+    }
+
+    fn initial_load(events: &RecordedEvents) -> Option<InitialInfo> {
+        events.all().into_iter().find_map(|event| match event {
+            LauncherEvent::InitialLoad(info) => Some(*info),
+            _ => None,
+        })
+    }
+
+    fn read(path: impl AsRef<Path>) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[tokio::test]
+    async fn fresh_install_downloads_everything() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = serve(release("1.0.0")).await;
+        let events = Arc::new(RecordedEvents::default());
+        let launcher = launcher(&server, dir.path(), events.clone());
+
+        launcher.initialize(DEFAULT_CHANNEL).await.unwrap();
+
+        let paths = &launcher.paths;
+        assert_eq!(read(paths.swf("stable", "1.0.0")), "stable 1.0.0");
+        assert_eq!(read(paths.swf("http", "1.0.0")), "http 1.0.0");
+        assert_eq!(read(paths.runtime(RUNTIME)), "runtime");
+        let local = get_local_versions(paths).unwrap();
+        assert_eq!(local.current_game_version, "1.0.0");
+        // The test server only speaks plain HTTP
+        assert!(!local.https_worked);
+
+        let info = initial_load(&events).unwrap();
+        assert!(!info.offline);
+        assert_eq!(info.manifest.current_game_version, "1.0.0");
+
+        let finished = events.all().into_iter().find_map(|event| match event {
+            LauncherEvent::DownloadsFinished(results) => Some(results),
+            _ => None,
+        });
+        let finished = finished.unwrap();
+        assert_eq!(finished.len(), 3);
+        assert!(finished.iter().all(|result| result.error.is_none()));
+    }
+
+    #[tokio::test]
+    async fn version_bump_replaces_old_swfs() {
+        let dir = tempfile::tempdir().unwrap();
+        let events = Arc::new(RecordedEvents::default());
+
+        let old = serve(release("1.0.0")).await;
+        launcher(&old, dir.path(), events.clone())
+            .initialize(DEFAULT_CHANNEL)
+            .await
+            .unwrap();
+
+        let new = serve(release("1.1.0")).await;
+        let launcher = launcher(&new, dir.path(), events.clone());
+        launcher.initialize(DEFAULT_CHANNEL).await.unwrap();
+
+        let paths = &launcher.paths;
+        assert_eq!(read(paths.swf("stable", "1.1.0")), "stable 1.1.0");
+        assert!(!paths.swf("stable", "1.0.0").exists());
+        assert_eq!(
+            get_local_versions(paths).unwrap().current_game_version,
+            "1.1.0"
+        );
+
+        // The runtime didn't change, so it isn't downloaded again
+        assert_eq!(requests_for(&new, "/downloads/flashplayer"), 0);
+    }
+
+    #[tokio::test]
+    async fn missing_swf_is_downloaded_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = serve(release("1.0.0")).await;
+        let events = Arc::new(RecordedEvents::default());
+        let launcher = launcher(&server, dir.path(), events);
+
+        launcher.initialize(DEFAULT_CHANNEL).await.unwrap();
+        fs::remove_file(launcher.paths.swf("stable", "1.0.0")).unwrap();
+        launcher.initialize(DEFAULT_CHANNEL).await.unwrap();
+
+        assert_eq!(read(launcher.paths.swf("stable", "1.0.0")), "stable 1.0.0");
+        assert_eq!(requests_for(&server, "/downloads/bymr-stable.swf"), 2);
+        assert_eq!(requests_for(&server, "/downloads/flashplayer"), 1);
+    }
+
+    #[tokio::test]
+    async fn swapped_swf_is_not_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut files = release("1.0.0");
+        files.insert(
+            "/downloads/bymr-stable.swf".to_string(),
+            Reply::new(200, "something else"),
+        );
+        let server = serve(files).await;
+        let events = Arc::new(RecordedEvents::default());
+        let launcher = launcher(&server, dir.path(), events);
+
+        launcher.initialize(DEFAULT_CHANNEL).await.unwrap();
+
+        assert!(!launcher.paths.swf("stable", "1.0.0").exists());
+        assert_eq!(read(launcher.paths.swf("http", "1.0.0")), "http 1.0.0");
+        // Fetched once more in case the first download was damaged
+        assert_eq!(requests_for(&server, "/downloads/bymr-stable.swf"), 2);
+    }
+
+    #[tokio::test]
+    async fn server_error_without_downloads_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = failing_server(503).await;
+        let events = Arc::new(RecordedEvents::default());
+
+        let err = launcher(&server, dir.path(), events.clone())
+            .initialize(DEFAULT_CHANNEL)
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            LauncherError::HttpStatus {
+                url: server.url("launcher.json"),
+                status: 503,
+            }
+        );
+        // Retried once before giving up
+        assert_eq!(requests_for(&server, "/launcher.json"), 2);
+        assert!(initial_load(&events).is_none());
+    }
+
+    #[tokio::test]
+    async fn server_error_falls_back_to_last_download() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let online = serve(release("1.0.0")).await;
+        launcher(&online, dir.path(), Arc::new(RecordedEvents::default()))
+            .initialize(DEFAULT_CHANNEL)
+            .await
+            .unwrap();
+
+        let offline = failing_server(503).await;
+        let events = Arc::new(RecordedEvents::default());
+        launcher(&offline, dir.path(), events.clone())
+            .initialize(DEFAULT_CHANNEL)
+            .await
+            .unwrap();
+
+        let info = initial_load(&events).unwrap();
+        assert!(info.offline);
+        assert_eq!(info.manifest.current_game_version, "1.0.0");
+    }
+}
@@ -1,184 +1,215 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod cli;
+mod error;
+mod events;
 mod file_manager;
+mod flash_debug;
+mod game_logs;
+mod http_client;
+mod integrity;
+mod launch_options;
+mod launcher;
+mod paths;
+mod preferences;
+mod retry;
+mod settings;
+mod signature;
+mod supervisor;
+#[cfg(test)]
+mod test_server;
+mod updater;
 mod version_manager;
 
-use crate::file_manager::{file_exists, set_local_versions};
+use crate::cli::Cli;
+use crate::error::LauncherError;
+use crate::events::StdoutEvents;
+use crate::file_manager::get_local_versions;
+use crate::flash_debug::{restore_mm_cfg, DebugSession};
+use crate::game_logs::{export_log, open_path, require_latest_log};
+use crate::http_client::build_http_client;
+use crate::launch_options::LaunchOptions;
+use crate::launcher::Launcher;
+use crate::paths::{legacy_download_roots, migrate_legacy_downloads, LauncherPaths};
+use crate::preferences::{Preferences, PreferencesStore};
+use crate::settings::Settings;
+use crate::supervisor::supervise;
 use crate::version_manager::*;
-use serde::{Deserialize, Serialize};
-use std::env;
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::sync::Arc;
 use tauri::{command, AppHandle, Manager};
 
-#[derive(Clone, Serialize)]
-struct Payload {
-    message: String,
-}
+fn main() {
+    let cli = Cli::parse_or_window(std::env::args_os()).unwrap_or_else(|err| {
+        cli::attach_console();
+        err.exit()
+    });
+    if cli.command.is_some() {
+        cli::attach_console();
+    }
+    let context = tauri::generate_context!();
+
+    let paths =
+        LauncherPaths::resolve(context.config()).expect("error while resolving app data directory");
+    match migrate_legacy_downloads(&paths, &legacy_download_roots()) {
+        Ok(Some(legacy)) => println!(
+            "Moved {} to {}",
+            legacy.display(),
+            paths.downloads().display()
+        ),
+        Ok(None) => {}
+        Err(err) => eprintln!("Could not migrate old downloads: {}", err),
+    }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-struct InitialInfo {
-    platform: String,
-    architecture: String,
-    manifest: VersionManifest,
-}
+    let settings = Settings::load(&paths, &cli.overrides);
+    let preferences = PreferencesStore::load(&paths);
+    let http_client = build_http_client().expect("error while creating http client");
+
+    // Subcommands run headless and print to stdout instead of opening the window
+    if let Some(command) = cli.command {
+        let launcher = Launcher::new(
+            paths,
+            settings,
+            http_client,
+            Arc::new(StdoutEvents::default()),
+        );
+        std::process::exit(cli::run(command, &launcher, &preferences));
+    }
+
+    // A debug launch the launcher was closed during leaves tracing enabled
+    restore_mm_cfg();
 
-fn main() {
     tauri::Builder::default()
-        .invoke_handler(tauri::generate_handler![initialize_app, launch_game])
-        .run(tauri::generate_context!())
+        .manage(paths)
+        .manage(settings)
+        .manage(preferences)
+        .manage(http_client)
+        .invoke_handler(tauri::generate_handler![
+            initialize_app,
+            launch_game,
+            list_installed_versions,
+            get_preferences,
+            set_channel,
+            set_keep_launcher_open,
+            open_latest_log,
+            export_latest_log
+        ])
+        .run(context)
         .expect("error while running tauri application");
 }
 
 #[command]
-async fn initialize_app(app: AppHandle) -> Result<(), String> {
+async fn initialize_app(app: AppHandle) -> Result<(), LauncherError> {
     println!("Tauri initialized");
 
-    // Get OS info
-    let message = format!("Platform: {} {}", env::consts::OS, env::consts::ARCH);
-    emit_event(&app, message);
-
-    let server_manifest = match get_version_info(&app).await {
-        Ok(manifest) => manifest,
-        Err(err) => {
-            let err_msg = format!("Server manifest could not be retrieved. Please check your internet connection. {:?}", err);
-            emit_event(&app, err_msg.clone());
-
-            return Err(err_msg);
-        }
-    };
-
-    let _ = app.emit_all(
-        "initialLoad",
-        InitialInfo {
-            platform: env::consts::OS.to_string(),
-            architecture: env::consts::ARCH.to_string(),
-            manifest: server_manifest.clone(),
-        },
-    );
+    let channel = app
+        .state::<PreferencesStore>()
+        .get()
+        .channel
+        .unwrap_or_else(|| DEFAULT_CHANNEL.to_string());
 
-    let (local_manifest_exists, local_manifest, err) = local_files_status();
+    Launcher::from_app(&app).initialize(&channel).await
+}
 
-    let no_local_manifest = !local_manifest_exists || !err.is_empty();
+#[command]
+fn get_preferences(app: AppHandle) -> Preferences {
+    app.state::<PreferencesStore>().get()
+}
 
-    let should_refresh_builds = no_local_manifest
-        || server_manifest.current_game_version != local_manifest.current_game_version
-        || !do_all_swfs_exist(
-            &server_manifest.builds,
-            &server_manifest.current_game_version,
-        );
+// The new channel is used the next time the app is initialized
+#[command]
+fn set_channel(app: AppHandle, channel: String) -> Result<(), LauncherError> {
+    app.state::<PreferencesStore>()
+        .update(|preferences| preferences.channel = Some(channel))
+}
 
-    // Download SWFs
-    if should_refresh_builds {
-        emit_event(&app, "Downloading latest SWFs".to_string());
-
-        if let Err(err) = download_swfs(
-            &server_manifest.builds,
-            &server_manifest.current_game_version,
-            server_manifest.https_worked,
-        )
-        .await
-        {
-            emit_event(&app, format!("Could not download latest swfs {}", err));
-        }
-    }
+#[command]
+fn set_keep_launcher_open(app: AppHandle, keep_open: bool) -> Result<(), LauncherError> {
+    app.state::<PreferencesStore>()
+        .update(|preferences| preferences.keep_launcher_open = keep_open)
+}
 
-    let flash_runtime_file_name =
-        match get_platform_flash_runtime(env::consts::OS, &server_manifest) {
-            Ok(file_name) => file_name,
-            Err(err) => {
-                let err_msg = format!("Could not download latest flash runtime {}", err);
-                emit_event(&app, err_msg);
+#[command]
+fn list_installed_versions(app: AppHandle) -> Vec<InstalledVersion> {
+    let paths = app.state::<LauncherPaths>();
+    let local_manifest = get_local_versions(&paths).unwrap_or_default();
 
-                return Err(err);
-            }
-        };
+    installed_versions(&paths, &local_manifest.current_game_version)
+}
 
-    let binding = PathBuf::from(RUNTIME_FOLDER).join(&flash_runtime_file_name);
-    let flash_runtime_path = binding.to_str().unwrap();
+#[command]
+async fn launch_game(
+    app: AppHandle,
+    build_name: String,
+    version: String,
+    runtime: String,
+    debug: bool,
+    options: LaunchOptions,
+) -> Result<(), LauncherError> {
+    let launcher = Launcher::from_app(&app);
+    let preferences = app.state::<PreferencesStore>();
+
+    // Otherwise the frontend closes the launcher right away. Debug launches
+    // stay open to show traces and restore mm.cfg afterwards.
+    let supervised = debug || preferences.get().keep_launcher_open;
+    let debug_session = if debug {
+        DebugSession::for_runtime(&launcher, &runtime)?
+    } else {
+        None
+    };
 
-    if no_local_manifest || !file_exists(&flash_runtime_path) {
-        let log = "Downloading flash player for your platform...";
-        emit_event(&app, log.to_string());
-        download_runtimes(&flash_runtime_file_name, server_manifest.https_worked).await?;
+    let (child, output) = launcher
+        .launch(&build_name, &version, &runtime, &options, supervised)
+        .await?;
+
+    // Remembered for the next launch of this build once the launcher accepted
+    // them. The game is already running, so failing to save isn't an error.
+    let saved = preferences.get();
+    if saved.launch_options(&build_name) != options
+        || saved.runtimes.get(&build_name) != Some(&runtime)
+    {
+        if let Err(err) = preferences.update(|preferences| {
+            preferences.set_launch_options(&build_name, options.clone());
+            preferences
+                .runtimes
+                .insert(build_name.clone(), runtime.clone());
+        }) {
+            eprintln!("{}", err);
+        }
     }
 
-    // Create an instance of LocalVersionManifest from serverManifest
-    let local_manifest = LocalVersionManifest {
-        current_game_version: server_manifest.current_game_version,
-        current_launcher_version: server_manifest.current_launcher_version,
-        builds: server_manifest.builds,
-        flash_runtimes: server_manifest.flash_runtimes,
-    };
-
-    match set_local_versions(local_manifest).await {
-        Ok(()) => println!("Local version manifest successfully written to file."),
-        Err(err) => println!("Version manifest error: {}", err),
+    if supervised {
+        supervise(
+            app,
+            child,
+            output,
+            build_name,
+            version,
+            runtime,
+            debug_session,
+        );
     }
 
     Ok(())
 }
 
 #[command]
-fn launch_game(build_name: &str, version: &str, runtime: &str) -> Result<(), String> {
-    let binding = PathBuf::from(".").join(RUNTIME_FOLDER).join(runtime);
-    let flash_runtime_path = binding.to_str().unwrap();
-
-    if !file_exists(&flash_runtime_path) {
-        eprintln!("cannot find file: {}", flash_runtime_path);
-        return Err(format!("cannot find flashplayer: {}", flash_runtime_path));
-    }
-
-    let binding = Path::new(".")
-        .join(BUILD_FOLDER)
-        .join(format!("bymr-{}-{}.swf", build_name, version));
-
-    let mut swf_path = String::from(binding.to_str().unwrap());
-
-    if !file_exists(&swf_path) {
-        eprintln!("Cannot find file: {:?}", swf_path);
-        return Err("cannot find swf build".to_string());
-    }
+fn open_latest_log(app: AppHandle) -> Result<(), LauncherError> {
+    let log = require_latest_log(&app.state::<LauncherPaths>().logs())?;
+    open_path(&log)
+}
 
-    // Linux
-    // Set the absolute path to SWF
-    // If error, set permissions to be executable for Flash runtime
-    if env::consts::OS == "linux" {
-        if let Ok(absolute_path) = std::fs::canonicalize(&swf_path) {
-            swf_path = absolute_path.to_str().unwrap().to_owned();
-
-            if let Err(perm_err) = Command::new("chmod")
-                .arg("+x")
-                .arg(&flash_runtime_path)
-                .output()
-            {
-                println!("Linux fix: could not run command: {:?}", perm_err);
-            }
-        }
-    }
-    println!("Opening: {:?}, {:?}", flash_runtime_path, swf_path);
-
-    // Open the game in Flash Player
-    Command::new(&flash_runtime_path)
-        .arg(&swf_path)
-        .spawn()
-        .map_err(|err| {
-            format!(
-                "[BYMR LAUNCHER] Failed to start BYMR build {}: {:?}",
-                build_name, err
-            )
-        })?;
+// Copies the latest log to the downloads folder, returning where it ended up
+#[command]
+fn export_latest_log(app: AppHandle) -> Result<String, LauncherError> {
+    let log = require_latest_log(&app.state::<LauncherPaths>().logs())?;
+    let folder = tauri::api::path::download_dir()
+        .or_else(tauri::api::path::home_dir)
+        .ok_or_else(|| LauncherError::MissingFile("the downloads folder".to_string()))?;
 
-    Ok(())
-}
+    let file_name = log.file_name().unwrap_or_default().to_string_lossy();
+    let destination = folder.join(format!("bymr-{}", file_name));
+    export_log(&log, &destination)?;
 
-pub fn emit_event(app: &AppHandle, message: String) {
-    app.emit_all(
-        "infoLog",
-        Payload {
-            message: message.to_string(),
-        },
-    )
-    .unwrap();
+    Ok(destination.display().to_string())
 }
@@ -0,0 +1,278 @@
+use crate::error::LauncherError;
+use crate::game_logs::LOGS_FOLDER;
+use crate::version_manager::{BUILD_FOLDER, DOWNLOADS_FOLDER, RUNTIME_FOLDER};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::Config;
+
+pub const VERSION_FILE: &str = "version.json";
+
+// An empty file with this name next to the executable turns on portable mode
+pub const PORTABLE_MARKER: &str = "portable";
+
+// Every file the launcher reads or writes lives under `data_dir`, so it
+// doesn't matter where the launcher is installed or started from
+#[derive(Debug, Clone)]
+pub struct LauncherPaths {
+    data_dir: PathBuf,
+    portable: bool,
+}
+
+impl LauncherPaths {
+    pub fn new(data_dir: impl Into<PathBuf>) -> Self {
+        LauncherPaths {
+            data_dir: data_dir.into(),
+            portable: false,
+        }
+    }
+
+    // Portable installs keep everything next to the executable
+    pub fn portable(exe_dir: impl Into<PathBuf>) -> Self {
+        LauncherPaths {
+            data_dir: exe_dir.into(),
+            portable: true,
+        }
+    }
+
+    // The platform app data directory, e.g. `%APPDATA%\com.bymr.launcher` on Windows,
+    // unless the launcher is running in portable mode
+    pub fn resolve(config: &Config) -> Result<Self, LauncherError> {
+        if let Some(exe_dir) = executable_dir().filter(|dir| is_portable_dir(dir)) {
+            return Ok(LauncherPaths::portable(exe_dir));
+        }
+
+        tauri::api::path::app_data_dir(config)
+            .map(LauncherPaths::new)
+            .ok_or_else(|| LauncherError::MissingFile("the app data directory".to_string()))
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    pub fn is_portable(&self) -> bool {
+        self.portable
+    }
+
+    pub fn downloads(&self) -> PathBuf {
+        self.data_dir.join(DOWNLOADS_FOLDER)
+    }
+
+    pub fn builds(&self) -> PathBuf {
+        self.downloads().join(BUILD_FOLDER)
+    }
+
+    pub fn runtimes(&self) -> PathBuf {
+        self.downloads().join(RUNTIME_FOLDER)
+    }
+
+    pub fn logs(&self) -> PathBuf {
+        self.downloads().join(LOGS_FOLDER)
+    }
+
+    pub fn version_file(&self) -> PathBuf {
+        self.downloads().join(VERSION_FILE)
+    }
+
+    pub fn swf(&self, build_name: &str, version: &str) -> PathBuf {
+        self.builds()
+            .join(format!("bymr-{}-{}.swf", build_name, version))
+    }
+
+    pub fn runtime(&self, file_name: &str) -> PathBuf {
+        self.runtimes().join(file_name)
+    }
+}
+
+fn executable_dir() -> Option<PathBuf> {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+}
+
+pub fn is_portable_dir(exe_dir: &Path) -> bool {
+    exe_dir.join(PORTABLE_MARKER).is_file()
+}
+
+// Most of the download code works with string paths
+pub fn path_string(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+// Older launchers kept their downloads in `./bymr-downloads`, relative to
+// wherever they were started from
+pub fn legacy_download_roots() -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+
+    if let Ok(current_dir) = env::current_dir() {
+        roots.push(current_dir);
+    }
+
+    if let Some(exe_dir) = executable_dir() {
+        if !roots.contains(&exe_dir) {
+            roots.push(exe_dir);
+        }
+    }
+
+    roots
+}
+
+// Moves the first legacy downloads folder found into the data directory.
+// Nothing happens once the data directory has its own downloads folder, so
+// this only ever runs once. Portable installs already use their own folder.
+pub fn migrate_legacy_downloads(
+    paths: &LauncherPaths,
+    legacy_roots: &[PathBuf],
+) -> Result<Option<PathBuf>, LauncherError> {
+    let target = paths.downloads();
+    if paths.is_portable() || target.exists() {
+        return Ok(None);
+    }
+
+    let legacy = match legacy_roots
+        .iter()
+        .map(|root| root.join(DOWNLOADS_FOLDER))
+        .find(|legacy| legacy.is_dir())
+    {
+        Some(legacy) => legacy,
+        None => return Ok(None),
+    };
+
+    fs::create_dir_all(paths.data_dir()).map_err(|err| {
+        LauncherError::io(
+            format!("Failed to create {}", paths.data_dir().display()),
+            err,
+        )
+    })?;
+
+    // Renames fail across drives, so fall back to copying
+    if fs::rename(&legacy, &target).is_err() {
+        if let Err(err) = copy_dir(&legacy, &target) {
+            let _ = fs::remove_dir_all(&target);
+            return Err(LauncherError::io(
+                format!(
+                    "Failed to move {} to {}",
+                    legacy.display(),
+                    target.display()
+                ),
+                err,
+            ));
+        }
+
+        // The copy is complete, a leftover legacy folder only wastes space
+        let _ = fs::remove_dir_all(&legacy);
+    }
+
+    Ok(Some(legacy))
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let destination = to.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &destination)?;
+        } else {
+            fs::copy(entry.path(), destination)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_everything_under_data_dir() {
+        let data_dir = Path::new("data").join("com.bymr.launcher");
+        let downloads = data_dir.join(DOWNLOADS_FOLDER);
+        let paths = LauncherPaths::new(&data_dir);
+
+        assert_eq!(paths.version_file(), downloads.join("version.json"));
+        assert_eq!(
+            paths.swf("stable", "1.2.3"),
+            downloads.join("swfs").join("bymr-stable-1.2.3.swf")
+        );
+        assert_eq!(
+            paths.runtime("flashplayer.exe"),
+            downloads.join("runtimes").join("flashplayer.exe")
+        );
+    }
+
+    #[test]
+    fn migrates_legacy_downloads_once() {
+        let legacy_root = tempfile::tempdir().unwrap();
+        let data_dir = tempfile::tempdir().unwrap();
+        let paths = LauncherPaths::new(data_dir.path().join("com.bymr.launcher"));
+
+        let legacy_builds = legacy_root.path().join(DOWNLOADS_FOLDER).join(BUILD_FOLDER);
+        fs::create_dir_all(&legacy_builds).unwrap();
+        fs::write(legacy_builds.join("bymr-stable-1.0.0.swf"), b"swf").unwrap();
+
+        let roots = vec![legacy_root.path().to_path_buf()];
+        let migrated = migrate_legacy_downloads(&paths, &roots).unwrap();
+
+        assert_eq!(migrated, Some(legacy_root.path().join(DOWNLOADS_FOLDER)));
+        assert_eq!(fs::read(paths.swf("stable", "1.0.0")).unwrap(), b"swf");
+        assert!(!legacy_root.path().join(DOWNLOADS_FOLDER).exists());
+
+        // A second legacy folder showing up later is left alone
+        fs::create_dir_all(&legacy_builds).unwrap();
+        assert_eq!(migrate_legacy_downloads(&paths, &roots).unwrap(), None);
+        assert!(legacy_builds.exists());
+    }
+
+    #[test]
+    fn detects_portable_marker() {
+        let exe_dir = tempfile::tempdir().unwrap();
+        assert!(!is_portable_dir(exe_dir.path()));
+
+        fs::write(exe_dir.path().join(PORTABLE_MARKER), b"").unwrap();
+        assert!(is_portable_dir(exe_dir.path()));
+
+        let paths = LauncherPaths::portable(exe_dir.path());
+        assert!(paths.is_portable());
+        assert_eq!(
+            paths.version_file(),
+            exe_dir.path().join(DOWNLOADS_FOLDER).join(VERSION_FILE)
+        );
+    }
+
+    #[test]
+    fn portable_mode_skips_migration() {
+        let legacy_root = tempfile::tempdir().unwrap();
+        let exe_dir = tempfile::tempdir().unwrap();
+        let paths = LauncherPaths::portable(exe_dir.path());
+
+        fs::create_dir_all(legacy_root.path().join(DOWNLOADS_FOLDER)).unwrap();
+
+        let roots = vec![legacy_root.path().to_path_buf()];
+        assert_eq!(migrate_legacy_downloads(&paths, &roots).unwrap(), None);
+        assert!(legacy_root.path().join(DOWNLOADS_FOLDER).exists());
+    }
+
+    #[test]
+    fn copies_directories_recursively() {
+        let from = tempfile::tempdir().unwrap();
+        let to = tempfile::tempdir().unwrap();
+        let target = to.path().join("copy");
+
+        fs::create_dir_all(from.path().join("runtimes")).unwrap();
+        fs::write(from.path().join("version.json"), b"{}").unwrap();
+        fs::write(from.path().join("runtimes").join("flashplayer"), b"bin").unwrap();
+
+        copy_dir(from.path(), &target).unwrap();
+
+        assert_eq!(fs::read(target.join("version.json")).unwrap(), b"{}");
+        assert_eq!(
+            fs::read(target.join("runtimes").join("flashplayer")).unwrap(),
+            b"bin"
+        );
+    }
+}
@@ -0,0 +1,224 @@
+use crate::error::LauncherError;
+use crate::file_manager::{ensure_folder_exists, write_atomic};
+use crate::launch_options::LaunchOptions;
+use crate::paths::LauncherPaths;
+use crate::supervisor::GameExit;
+use crate::version_manager::Runtime;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+pub const PREFERENCES_FILE: &str = "preferences.json";
+
+// Choices made in the launcher UI. Unlike `Settings`, these are written back
+// whenever the user changes them.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Preferences {
+    pub channel: Option<String>,
+    // Keeps the launcher minimized while the game runs, so crashes can be reported
+    pub keep_launcher_open: bool,
+    // Keyed by build name
+    pub launch_options: BTreeMap<String, LaunchOptions>,
+    // Runtime file picked for each build
+    pub runtimes: BTreeMap<String, String>,
+    // The last runtime the game exited cleanly in, used for builds nobody
+    // picked a runtime for
+    pub working_runtime: Option<String>,
+}
+
+impl Preferences {
+    pub fn launch_options(&self, build_name: &str) -> LaunchOptions {
+        self.launch_options
+            .get(build_name)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    // The picked runtime, then the one known to work, then the default.
+    // Runtimes the manifest no longer lists are skipped.
+    pub fn runtime_for(&self, build_name: &str, available: &[Runtime]) -> Option<String> {
+        let is_available = |file: &&String| available.iter().any(|runtime| runtime.file == **file);
+
+        self.runtimes
+            .get(build_name)
+            .filter(is_available)
+            .or_else(|| self.working_runtime.as_ref().filter(is_available))
+            .cloned()
+            .or_else(|| available.first().map(|runtime| runtime.file.clone()))
+    }
+
+    pub fn record_exit(&mut self, exit: &GameExit) {
+        if !exit.crashed {
+            self.working_runtime = Some(exit.runtime.clone());
+        } else if self.working_runtime.as_deref() == Some(exit.runtime.as_str()) {
+            self.working_runtime = None;
+        }
+    }
+
+    // Builds without options are left out, so the file only lists what the
+    // user changed
+    pub fn set_launch_options(&mut self, build_name: &str, options: LaunchOptions) {
+        if options.is_empty() {
+            self.launch_options.remove(build_name);
+        } else {
+            self.launch_options.insert(build_name.to_string(), options);
+        }
+    }
+}
+
+pub struct PreferencesStore {
+    path: PathBuf,
+    preferences: Mutex<Preferences>,
+}
+
+impl PreferencesStore {
+    // Unreadable preferences are replaced with the defaults rather than
+    // keeping the launcher from starting
+    pub fn load(paths: &LauncherPaths) -> Self {
+        let path = paths.downloads().join(PREFERENCES_FILE);
+
+        let preferences = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|err| {
+                eprintln!("Failed to parse {}: {}", path.display(), err);
+                Preferences::default()
+            }),
+            Err(_) => Preferences::default(),
+        };
+
+        PreferencesStore {
+            path,
+            preferences: Mutex::new(preferences),
+        }
+    }
+
+    pub fn get(&self) -> Preferences {
+        self.preferences.lock().unwrap().clone()
+    }
+
+    pub fn update(&self, change: impl FnOnce(&mut Preferences)) -> Result<(), LauncherError> {
+        let mut preferences = self.preferences.lock().unwrap();
+        change(&mut preferences);
+
+        let contents = serde_json::to_vec_pretty(&*preferences).map_err(|err| {
+            LauncherError::Decode(format!("Failed to encode preferences: {}", err))
+        })?;
+
+        if let Some(folder) = self.path.parent() {
+            ensure_folder_exists(folder)?;
+        }
+        write_atomic(&self.path, &contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persists_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = LauncherPaths::new(dir.path());
+
+        let store = PreferencesStore::load(&paths);
+        assert_eq!(store.get(), Preferences::default());
+
+        store
+            .update(|preferences| preferences.channel = Some("beta".to_string()))
+            .unwrap();
+
+        let reloaded = PreferencesStore::load(&paths);
+        assert_eq!(reloaded.get().channel.as_deref(), Some("beta"));
+    }
+
+    #[test]
+    fn keeps_launch_options_per_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = LauncherPaths::new(dir.path());
+        let store = PreferencesStore::load(&paths);
+
+        let mut options = LaunchOptions::default();
+        options
+            .flashvars
+            .insert("server".to_string(), "http://localhost:3001".to_string());
+        store
+            .update(|preferences| preferences.set_launch_options("http", options.clone()))
+            .unwrap();
+
+        let reloaded = PreferencesStore::load(&paths).get();
+        assert_eq!(reloaded.launch_options("http"), options);
+        assert_eq!(reloaded.launch_options("stable"), LaunchOptions::default());
+
+        store
+            .update(|preferences| preferences.set_launch_options("http", LaunchOptions::default()))
+            .unwrap();
+        assert!(PreferencesStore::load(&paths)
+            .get()
+            .launch_options
+            .is_empty());
+    }
+
+    #[test]
+    fn picks_runtime_per_build() {
+        let runtime = |file: &str| Runtime {
+            file: file.to_string(),
+            ..Runtime::default()
+        };
+        let available = vec![runtime("flashplayer"), runtime("ruffle")];
+        let exit = |runtime: &str, crashed: bool| GameExit {
+            build_name: "stable".to_string(),
+            version: "1.0.0".to_string(),
+            runtime: runtime.to_string(),
+            code: Some(if crashed { 1 } else { 0 }),
+            signal: None,
+            duration_seconds: 60.0,
+            crashed,
+        };
+
+        let mut preferences = Preferences::default();
+        assert_eq!(
+            preferences.runtime_for("stable", &available).as_deref(),
+            Some("flashplayer")
+        );
+
+        preferences.record_exit(&exit("ruffle", false));
+        assert_eq!(
+            preferences.runtime_for("stable", &available).as_deref(),
+            Some("ruffle")
+        );
+
+        preferences
+            .runtimes
+            .insert("stable".to_string(), "flashplayer".to_string());
+        preferences
+            .runtimes
+            .insert("http".to_string(), "removed".to_string());
+        assert_eq!(
+            preferences.runtime_for("stable", &available).as_deref(),
+            Some("flashplayer")
+        );
+        assert_eq!(
+            preferences.runtime_for("http", &available).as_deref(),
+            Some("ruffle")
+        );
+
+        // A crash in another runtime doesn't change what is known to work
+        preferences.record_exit(&exit("flashplayer", true));
+        assert_eq!(preferences.working_runtime.as_deref(), Some("ruffle"));
+        preferences.record_exit(&exit("ruffle", true));
+        assert_eq!(preferences.working_runtime, None);
+        assert_eq!(preferences.runtime_for("event", &[]), None);
+    }
+
+    #[test]
+    fn ignores_corrupt_preferences() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = LauncherPaths::new(dir.path());
+        fs::create_dir_all(paths.downloads()).unwrap();
+        fs::write(paths.downloads().join(PREFERENCES_FILE), b"{").unwrap();
+
+        assert_eq!(PreferencesStore::load(&paths).get(), Preferences::default());
+    }
+}
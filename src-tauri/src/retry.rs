@@ -0,0 +1,338 @@
+use crate::error::LauncherError;
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, SystemTime};
+
+// Statuses that usually mean "try again later" rather than "this will never work"
+const RETRYABLE_STATUS_CODES: [StatusCode; 4] = [
+    StatusCode::TOO_MANY_REQUESTS,
+    StatusCode::BAD_GATEWAY,
+    StatusCode::SERVICE_UNAVAILABLE,
+    StatusCode::GATEWAY_TIMEOUT,
+];
+
+// Don't let a misbehaving server park the launcher for minutes
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    // How much of the delay is random, between 0 and 1
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.5,
+        }
+    }
+}
+
+// The error of a single attempt, telling the policy whether it is worth trying again
+#[derive(Debug, Clone)]
+pub struct AttemptError {
+    pub error: LauncherError,
+    pub retryable: bool,
+    pub retry_after: Option<Duration>,
+}
+
+impl AttemptError {
+    pub fn fatal(error: LauncherError) -> Self {
+        AttemptError {
+            error,
+            retryable: false,
+            retry_after: None,
+        }
+    }
+
+    pub fn transient(error: LauncherError) -> Self {
+        AttemptError {
+            error,
+            retryable: true,
+            retry_after: None,
+        }
+    }
+
+    pub fn from_status(status: StatusCode, headers: &HeaderMap, url: &str) -> Self {
+        AttemptError {
+            error: LauncherError::HttpStatus {
+                url: url.to_string(),
+                status: status.as_u16(),
+            },
+            retryable: is_retryable_status(status),
+            retry_after: parse_retry_after(headers),
+        }
+    }
+}
+
+impl fmt::Display for AttemptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+pub struct RetryNotice<'a> {
+    pub label: &'a str,
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay: Duration,
+    pub error: &'a AttemptError,
+}
+
+impl fmt::Display for RetryNotice<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} failed ({}), retrying in {:.1}s (attempt {}/{})",
+            self.label,
+            self.error,
+            self.delay.as_secs_f64(),
+            self.attempt + 1,
+            self.max_attempts
+        )
+    }
+}
+
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    RETRYABLE_STATUS_CODES.contains(&status)
+}
+
+// Retry-After is either a number of seconds or an HTTP date
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+impl RetryPolicy {
+    // Exponential backoff with jitter, unless the server told us how long to wait
+    pub fn delay_for(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(MAX_RETRY_AFTER);
+        }
+
+        let exponent = attempt.saturating_sub(1).min(16);
+        let backoff = self
+            .base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 - jitter * rand::thread_rng().gen_range(0.0..=1.0);
+        backoff.mul_f64(factor)
+    }
+
+    // Runs `operation` until it succeeds, fails for good or runs out of
+    // attempts, calling `on_retry` before each new attempt
+    pub async fn run<T, F, Fut, R>(
+        &self,
+        label: &str,
+        mut operation: F,
+        mut on_retry: R,
+    ) -> Result<T, LauncherError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, AttemptError>>,
+        R: FnMut(&RetryNotice),
+    {
+        let max_attempts = self.max_attempts.max(1);
+        let mut attempt = 1;
+
+        loop {
+            let error = match operation().await {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+
+            if !error.retryable || attempt >= max_attempts {
+                return Err(error.error);
+            }
+
+            let delay = self.delay_for(attempt, error.retry_after);
+            on_retry(&RetryNotice {
+                label,
+                attempt,
+                max_attempts,
+                delay,
+                error: &error,
+            });
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use std::cell::Cell;
+
+    fn quick_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+            jitter: 0.0,
+        }
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            jitter: 0.0,
+        };
+
+        assert_eq!(policy.delay_for(1, None), Duration::from_millis(100));
+        assert_eq!(policy.delay_for(2, None), Duration::from_millis(200));
+        assert_eq!(policy.delay_for(3, None), Duration::from_millis(400));
+        assert_eq!(policy.delay_for(8, None), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn jitter_stays_within_backoff() {
+        let policy = RetryPolicy {
+            jitter: 1.0,
+            ..RetryPolicy::default()
+        };
+
+        for _ in 0..100 {
+            assert!(policy.delay_for(1, None) <= policy.base_delay);
+        }
+    }
+
+    #[test]
+    fn retry_after_overrides_backoff() {
+        let policy = quick_policy();
+        assert_eq!(
+            policy.delay_for(1, Some(Duration::from_secs(3))),
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            policy.delay_for(1, Some(Duration::from_secs(3600))),
+            MAX_RETRY_AFTER
+        );
+    }
+
+    #[test]
+    fn parses_retry_after_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("7"));
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(7)));
+
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(parse_retry_after(&headers), Some(Duration::ZERO));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(parse_retry_after(&headers), None);
+    }
+
+    #[test]
+    fn only_transient_statuses_are_retryable() {
+        assert!(is_retryable_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_retryable_status(StatusCode::NOT_FOUND));
+        assert!(!is_retryable_status(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    #[tokio::test]
+    async fn retries_transient_errors_until_success() {
+        let calls = Cell::new(0);
+        let mut notices = Vec::new();
+
+        let result = quick_policy()
+            .run(
+                "launcher.json",
+                || {
+                    calls.set(calls.get() + 1);
+                    let attempt = calls.get();
+                    async move {
+                        match attempt {
+                            3 => Ok("manifest"),
+                            _ => Err(AttemptError::transient(LauncherError::Network(
+                                "connection reset".to_string(),
+                            ))),
+                        }
+                    }
+                },
+                |notice| notices.push(notice.to_string()),
+            )
+            .await;
+
+        assert_eq!(result, Ok("manifest"));
+        assert_eq!(calls.get(), 3);
+        assert_eq!(notices.len(), 2);
+        assert!(notices[0].starts_with("launcher.json failed (Network error: connection reset)"));
+    }
+
+    #[tokio::test]
+    async fn stops_on_fatal_error() {
+        let calls = Cell::new(0);
+
+        let not_found = LauncherError::HttpStatus {
+            url: "launcher.json".to_string(),
+            status: 404,
+        };
+
+        let result: Result<(), LauncherError> = quick_policy()
+            .run(
+                "launcher.json",
+                || {
+                    calls.set(calls.get() + 1);
+                    let error = AttemptError::fatal(not_found.clone());
+                    async { Err(error) }
+                },
+                |_| {},
+            )
+            .await;
+
+        assert_eq!(result, Err(not_found));
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let calls = Cell::new(0);
+
+        let timed_out = LauncherError::Network("timed out".to_string());
+
+        let result: Result<(), LauncherError> = quick_policy()
+            .run(
+                "launcher.json",
+                || {
+                    calls.set(calls.get() + 1);
+                    let error = AttemptError::transient(timed_out.clone());
+                    async { Err(error) }
+                },
+                |_| {},
+            )
+            .await;
+
+        assert_eq!(result, Err(timed_out));
+        assert_eq!(calls.get(), 3);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::error::LauncherError;
+use crate::paths::LauncherPaths;
+use crate::signature::MANIFEST_PUBKEY;
+use crate::version_manager::{
+    DEFAULT_KEEP_PREVIOUS_VERSIONS, DEFAULT_MAX_CONCURRENT_DOWNLOADS, DOWNLOAD_BASE_PATH,
+    VERSION_INFO_PATH_BASE,
+};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+pub const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    // Endpoints may include a scheme (e.g. `http://localhost:3001/launcher.json`).
+    // Without one, https is tried first and http is used as a fallback.
+    pub manifest_url: String,
+    pub download_url: String,
+    pub max_concurrent_downloads: usize,
+    // How many versions besides the current one are kept on disk
+    pub keep_previous_versions: usize,
+    // Private servers sign their manifest with their own key. Release builds
+    // ignore the settings file here and always trust the embedded key.
+    #[cfg_attr(not(debug_assertions), serde(skip))]
+    pub manifest_public_key: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            manifest_url: VERSION_INFO_PATH_BASE.to_string(),
+            download_url: DOWNLOAD_BASE_PATH.to_string(),
+            max_concurrent_downloads: DEFAULT_MAX_CONCURRENT_DOWNLOADS,
+            keep_previous_versions: DEFAULT_KEEP_PREVIOUS_VERSIONS,
+            manifest_public_key: MANIFEST_PUBKEY.to_string(),
+        }
+    }
+}
+
+// Command line flags take precedence over environment variables, which take
+// precedence over the settings file
+#[derive(Debug, Default, Args)]
+pub struct Overrides {
+    /// URL of the launcher manifest
+    #[arg(long, global = true, env = "BYMR_MANIFEST_URL")]
+    pub manifest_url: Option<String>,
+
+    /// Base URL that SWFs and runtimes are downloaded from
+    #[arg(long, global = true, env = "BYMR_DOWNLOAD_URL")]
+    pub download_url: Option<String>,
+
+    /// How many files are downloaded at the same time
+    #[arg(long, global = true, env = "BYMR_MAX_CONCURRENT_DOWNLOADS")]
+    pub max_concurrent_downloads: Option<usize>,
+
+    /// How many previous game versions are kept when a new one is downloaded
+    #[arg(long, global = true, env = "BYMR_KEEP_PREVIOUS_VERSIONS")]
+    pub keep_previous_versions: Option<usize>,
+
+    // Swapping the trust root is only possible in debug builds
+    /// Base64 encoded minisign public key the manifest is signed with
+    #[cfg(debug_assertions)]
+    #[arg(long, global = true, env = "BYMR_MANIFEST_PUBLIC_KEY")]
+    pub manifest_public_key: Option<String>,
+}
+
+impl Settings {
+    pub fn load(paths: &LauncherPaths, overrides: &Overrides) -> Self {
+        let path = paths.downloads().join(SETTINGS_FILE);
+
+        let settings = match Settings::from_file(&path) {
+            Ok(settings) => settings,
+            Err(err) => {
+                eprintln!("{}, using default settings", err);
+                Settings::default()
+            }
+        };
+
+        settings.with_overrides(overrides)
+    }
+
+    // A missing settings file is fine, the defaults point at the official server
+    pub fn from_file(path: &Path) -> Result<Self, LauncherError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Settings::default())
+            }
+            Err(err) => {
+                return Err(LauncherError::io(
+                    format!("Failed to read {}", path.display()),
+                    err,
+                ))
+            }
+        };
+
+        serde_json::from_str(&contents).map_err(|err| {
+            LauncherError::Decode(format!("Failed to parse {}: {}", path.display(), err))
+        })
+    }
+
+    pub fn with_overrides(mut self, overrides: &Overrides) -> Self {
+        if let Some(manifest_url) = &overrides.manifest_url {
+            self.manifest_url = manifest_url.clone();
+        }
+        if let Some(download_url) = &overrides.download_url {
+            self.download_url = download_url.clone();
+        }
+        if let Some(max_concurrent_downloads) = overrides.max_concurrent_downloads {
+            self.max_concurrent_downloads = max_concurrent_downloads;
+        }
+        if let Some(keep_previous_versions) = overrides.keep_previous_versions {
+            self.keep_previous_versions = keep_previous_versions;
+        }
+        #[cfg(debug_assertions)]
+        if let Some(manifest_public_key) = &overrides.manifest_public_key {
+            self.manifest_public_key = manifest_public_key.clone();
+        }
+
+        self.max_concurrent_downloads = self.max_concurrent_downloads.max(1);
+        self
+    }
+
+    pub fn manifest_url(&self, use_https: bool) -> String {
+        with_scheme(&self.manifest_url, use_https)
+    }
+
+    // Only endpoints without a scheme get the http fallback
+    pub fn manifest_has_http_fallback(&self) -> bool {
+        !has_scheme(&self.manifest_url)
+    }
+
+    pub fn download_url(&self, file_name: &str, use_https: bool) -> String {
+        let base = with_scheme(&self.download_url, use_https);
+        format!("{}/{}", base.trim_end_matches('/'), file_name)
+    }
+}
+
+fn has_scheme(endpoint: &str) -> bool {
+    endpoint.contains("://")
+}
+
+fn with_scheme(endpoint: &str, use_https: bool) -> String {
+    if has_scheme(endpoint) {
+        return endpoint.to_string();
+    }
+
+    let scheme = if use_https { "https" } else { "http" };
+    format!("{}://{}", scheme, endpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::Parser;
+
+    #[test]
+    fn defaults_point_at_official_server() {
+        let settings = Settings::default();
+
+        assert_eq!(
+            settings.manifest_url(true),
+            "https://api.bymrefitted.com/launcher.json"
+        );
+        assert_eq!(
+            settings.download_url("flashplayer.exe", false),
+            "http://api.bymrefitted.com/launcher/downloads/flashplayer.exe"
+        );
+        assert!(settings.manifest_has_http_fallback());
+    }
+
+    #[test]
+    fn explicit_scheme_is_kept() {
+        let settings = Settings {
+            manifest_url: "http://localhost:3001/launcher.json".to_string(),
+            download_url: "http://localhost:3001/downloads".to_string(),
+            ..Settings::default()
+        };
+
+        assert_eq!(
+            settings.manifest_url(true),
+            "http://localhost:3001/launcher.json"
+        );
+        assert_eq!(
+            settings.download_url("bymr-stable.swf", true),
+            "http://localhost:3001/downloads/bymr-stable.swf"
+        );
+        assert!(!settings.manifest_has_http_fallback());
+    }
+
+    #[test]
+    fn reads_partial_settings_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SETTINGS_FILE);
+        fs::write(
+            &path,
+            r#"{"manifestUrl": "staging.bymrefitted.com/launcher.json"}"#,
+        )
+        .unwrap();
+
+        let settings = Settings::from_file(&path).unwrap();
+        assert_eq!(
+            settings.manifest_url,
+            "staging.bymrefitted.com/launcher.json"
+        );
+        assert_eq!(settings.download_url, DOWNLOAD_BASE_PATH);
+    }
+
+    #[test]
+    fn missing_settings_file_uses_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = Settings::from_file(&dir.path().join(SETTINGS_FILE)).unwrap();
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn overrides_take_precedence_over_file() {
+        let file = Settings {
+            manifest_url: "staging.bymrefitted.com/launcher.json".to_string(),
+            max_concurrent_downloads: 5,
+            ..Settings::default()
+        };
+
+        let overrides = Cli::parse_from([
+            "bymr-launcher",
+            "--manifest-url",
+            "http://localhost:3001/launcher.json",
+            "--max-concurrent-downloads=0",
+        ])
+        .overrides;
+        let settings = file.with_overrides(&overrides);
+
+        assert_eq!(settings.manifest_url, "http://localhost:3001/launcher.json");
+        assert_eq!(settings.download_url, DOWNLOAD_BASE_PATH);
+        assert_eq!(settings.max_concurrent_downloads, 1);
+    }
+}
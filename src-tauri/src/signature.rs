@@ -0,0 +1,157 @@
+use crate::error::LauncherError;
+use base64::Engine;
+use minisign_verify::{PublicKey, Signature};
+
+// The launcher manifest is signed with the same minisign key as our updater
+// artifacts (see `tauri.updater.pubkey` in tauri.conf.json), e.g. with
+// `npm run tauri signer sign launcher.json`
+pub const MANIFEST_PUBKEY: &str = "dW50cnVzdGVkIGNvbW1lbnQ6IG1pbmlzaWduIHB1YmxpYyBrZXk6IEJBQzY1N0EzQzJCRUNBQzkKUldUSnlyN0NvMWZHdXB3dml4WGNvdHdRZVZraWpkVks0UVhHVTZvMlkvSjBlZlRQOGd1RVMxRk0K";
+
+// Tauri's signer writes `.sig` files as base64 encoded minisign signatures,
+// but we also accept the plain minisign format
+fn decode_signature(signature: &str) -> Result<Signature, LauncherError> {
+    let signature = signature.trim();
+
+    let decoded = if signature.starts_with("untrusted comment:") {
+        signature.to_string()
+    } else {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(signature)
+            .map_err(|err| {
+                LauncherError::Decode(format!("Manifest signature is not valid base64: {}", err))
+            })?;
+        String::from_utf8(bytes).map_err(|_| {
+            LauncherError::Decode("Manifest signature is not valid UTF-8".to_string())
+        })?
+    };
+
+    Signature::decode(&decoded)
+        .map_err(|err| LauncherError::Decode(format!("Invalid manifest signature: {}", err)))
+}
+
+fn decode_public_key(pubkey: &str) -> Result<PublicKey, LauncherError> {
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(pubkey.trim())
+        .map_err(|err| {
+            LauncherError::Decode(format!("Manifest public key is not valid base64: {}", err))
+        })?;
+    let decoded = String::from_utf8(bytes)
+        .map_err(|_| LauncherError::Decode("Manifest public key is not valid UTF-8".to_string()))?;
+
+    PublicKey::decode(&decoded)
+        .map_err(|err| LauncherError::Decode(format!("Invalid manifest public key: {}", err)))
+}
+
+pub fn verify_manifest_signature(
+    body: &[u8],
+    signature: &str,
+    pubkey: &str,
+) -> Result<(), LauncherError> {
+    let public_key = decode_public_key(pubkey)?;
+    let signature = decode_signature(signature)?;
+
+    // Only accept prehashed signatures, which is what current minisign and tauri produce
+    public_key.verify(body, &signature, false).map_err(|err| {
+        LauncherError::Integrity(format!("Manifest signature verification failed: {}", err))
+    })
+}
+
+#[cfg(test)]
+pub mod testing {
+    use base64::Engine;
+    use blake2::{Blake2b512, Digest};
+    use ed25519_dalek::{Signer, SigningKey};
+
+    const KEY_ID: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[7u8; 32])
+    }
+
+    fn encode(data: impl AsRef<[u8]>) -> String {
+        base64::engine::general_purpose::STANDARD.encode(data)
+    }
+
+    // The test public key, encoded the same way as `MANIFEST_PUBKEY`
+    pub fn public_key() -> String {
+        let mut bin = b"Ed".to_vec();
+        bin.extend_from_slice(&KEY_ID);
+        bin.extend_from_slice(signing_key().verifying_key().as_bytes());
+
+        encode(format!(
+            "untrusted comment: minisign public key: test\n{}\n",
+            encode(bin)
+        ))
+    }
+
+    // Signs `body` like `tauri signer sign` would, returning the `.sig` contents
+    pub fn sign(body: &[u8]) -> String {
+        let key = signing_key();
+        let digest = Blake2b512::digest(body);
+        let signature = key.sign(&digest).to_bytes();
+
+        let mut bin = b"ED".to_vec();
+        bin.extend_from_slice(&KEY_ID);
+        bin.extend_from_slice(&signature);
+
+        let trusted_comment = "timestamp:0\tfile:launcher.json";
+        let mut global = signature.to_vec();
+        global.extend_from_slice(trusted_comment.as_bytes());
+        let global_signature = key.sign(&global).to_bytes();
+
+        encode(format!(
+            "untrusted comment: signature from tauri secret key\n{}\ntrusted comment: {}\n{}\n",
+            encode(bin),
+            trusted_comment,
+            encode(global_signature)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &[u8] = br#"{"currentGameVersion":"1.0.0"}"#;
+
+    #[test]
+    fn accepts_valid_signature() {
+        let signature = testing::sign(BODY);
+        assert!(verify_manifest_signature(BODY, &signature, &testing::public_key()).is_ok());
+    }
+
+    #[test]
+    fn accepts_plain_minisign_signature() {
+        let signature = base64::engine::general_purpose::STANDARD
+            .decode(testing::sign(BODY))
+            .unwrap();
+        let signature = String::from_utf8(signature).unwrap();
+
+        assert!(verify_manifest_signature(BODY, &signature, &testing::public_key()).is_ok());
+    }
+
+    #[test]
+    fn rejects_tampered_body() {
+        let signature = testing::sign(BODY);
+        let tampered = br#"{"currentGameVersion":"6.6.6"}"#;
+
+        let err = verify_manifest_signature(tampered, &signature, &testing::public_key());
+        assert_eq!(err.unwrap_err().code(), "integrity");
+    }
+
+    #[test]
+    fn rejects_signature_from_another_key() {
+        let signature = testing::sign(BODY);
+        assert!(verify_manifest_signature(BODY, &signature, MANIFEST_PUBKEY).is_err());
+    }
+
+    #[test]
+    fn rejects_garbage_signature() {
+        assert!(verify_manifest_signature(BODY, "not a signature", MANIFEST_PUBKEY).is_err());
+    }
+
+    #[test]
+    fn embedded_public_key_decodes() {
+        assert!(decode_public_key(MANIFEST_PUBKEY).is_ok());
+    }
+}
@@ -0,0 +1,173 @@
+use crate::error::LauncherError;
+use crate::events::LauncherEvent;
+use crate::flash_debug::DebugSession;
+use crate::game_logs::OutputCapture;
+use crate::launcher::Launcher;
+use crate::preferences::PreferencesStore;
+use serde::Serialize;
+use std::process::{Child, ExitStatus};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+const MAIN_WINDOW: &str = "main";
+
+// How a game session ended, shown in the crash dialog when it didn't end well
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameExit {
+    pub build_name: String,
+    pub version: String,
+    // File name of the runtime the game ran in
+    pub runtime: String,
+    pub code: Option<i32>,
+    // Only set on unix, when the game was killed by a signal
+    pub signal: Option<i32>,
+    pub duration_seconds: f64,
+    pub crashed: bool,
+}
+
+impl GameExit {
+    pub fn new(
+        build_name: &str,
+        version: &str,
+        runtime: &str,
+        status: ExitStatus,
+        duration: Duration,
+    ) -> Self {
+        GameExit {
+            build_name: build_name.to_string(),
+            version: version.to_string(),
+            runtime: runtime.to_string(),
+            code: status.code(),
+            signal: exit_signal(&status),
+            duration_seconds: duration.as_secs_f64(),
+            crashed: !status.success(),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn exit_signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+// Blocks until the game exits and its output has been logged
+pub fn wait_for_exit(
+    mut child: Child,
+    output: OutputCapture,
+    build_name: &str,
+    version: &str,
+    runtime: &str,
+) -> Result<GameExit, LauncherError> {
+    let started = Instant::now();
+    let status = child
+        .wait()
+        .map_err(|err| LauncherError::io("Failed to wait for the game", err))?;
+    output.finish();
+
+    let exit = GameExit::new(build_name, version, runtime, status, started.elapsed());
+    println!(
+        "Game exited with {} after {:.0}s",
+        status, exit.duration_seconds
+    );
+    Ok(exit)
+}
+
+// Keeps the launcher minimized while the game runs, then brings it back
+// and reports how the game exited. A debug session ends with the game.
+pub fn supervise(
+    app: AppHandle,
+    child: Child,
+    output: OutputCapture,
+    build_name: String,
+    version: String,
+    runtime: String,
+    debug_session: Option<DebugSession>,
+) {
+    if let Some(window) = app.get_window(MAIN_WINDOW) {
+        let _ = window.minimize();
+    }
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let launcher = Launcher::from_app(&app);
+
+        let exit = wait_for_exit(child, output, &build_name, &version, &runtime);
+        drop(debug_session);
+
+        let exit = match exit {
+            Ok(exit) => exit,
+            Err(err) => {
+                launcher.info(err.to_string());
+                return;
+            }
+        };
+
+        if let Some(window) = app.get_window(MAIN_WINDOW) {
+            let _ = window.unminimize();
+            if exit.crashed {
+                let _ = window.set_focus();
+            }
+        }
+
+        if let Err(err) = app
+            .state::<PreferencesStore>()
+            .update(|preferences| preferences.record_exit(&exit))
+        {
+            eprintln!("{}", err);
+        }
+
+        launcher.emit(LauncherEvent::GameExited(exit));
+    });
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn run(script: &str) -> GameExit {
+        let child = Command::new("sh").arg("-c").arg(script).spawn().unwrap();
+        wait_for_exit(
+            child,
+            OutputCapture::default(),
+            "stable",
+            "1.0.0",
+            "flashplayer",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn clean_exit_is_not_a_crash() {
+        let exit = run("exit 0");
+
+        assert_eq!(exit.code, Some(0));
+        assert_eq!(exit.signal, None);
+        assert!(!exit.crashed);
+        assert_eq!(exit.build_name, "stable");
+        assert_eq!(exit.runtime, "flashplayer");
+    }
+
+    #[test]
+    fn records_exit_code() {
+        let exit = run("exit 3");
+
+        assert_eq!(exit.code, Some(3));
+        assert!(exit.crashed);
+    }
+
+    #[test]
+    fn records_signal() {
+        let exit = run("kill -9 $$");
+
+        assert_eq!(exit.code, None);
+        assert_eq!(exit.signal, Some(9));
+        assert!(exit.crashed);
+    }
+}
@@ -0,0 +1,433 @@
+use crate::error::LauncherError;
+use crate::events::LauncherEvent;
+use crate::file_manager::set_local_versions;
+use crate::integrity::{require_sha256, FileIntegrity};
+use crate::launcher::Launcher;
+use crate::paths::{path_string, LauncherPaths};
+use crate::version_manager::*;
+
+// What has to happen to bring the local files in line with the server manifest
+#[derive(Debug, Clone)]
+pub struct UpdatePlan {
+    pub downloads: Vec<DownloadJob>,
+    // Set when the SWFs of the current version are missing or outdated
+    pub refresh_builds: bool,
+    // The platform's default runtime, nothing can be launched without it
+    pub runtime_file: String,
+    pub download_runtime: bool,
+    // Every runtime of the platform, including the default one
+    pub runtime_files: Vec<String>,
+    // Old versions that are deleted once every new SWF has been downloaded
+    pub remove: Vec<InstalledVersion>,
+    pub use_https: bool,
+    // Written once the update is done, so the next start knows what is on disk
+    pub local_manifest: LocalVersionManifest,
+}
+
+// Works out an update plan from the manifests and the installed versions,
+// without touching the network or the disk
+pub struct Updater<'a> {
+    pub paths: &'a LauncherPaths,
+    pub server: &'a VersionManifest,
+    pub local: Option<&'a LocalVersionManifest>,
+    pub installed: &'a [InstalledVersion],
+    pub keep_previous: usize,
+}
+
+impl Updater<'_> {
+    // `is_valid` tells whether a file is on disk and matches its checksum
+    pub fn plan<F>(&self, platform: &str, is_valid: F) -> Result<UpdatePlan, LauncherError>
+    where
+        F: Fn(&str, Option<&FileIntegrity>) -> bool,
+    {
+        let server = self.server;
+        let version = &server.current_game_version;
+        let checksums = &server.checksums;
+
+        let runtime_files: Vec<String> = get_platform_runtimes(platform, server)?
+            .into_iter()
+            .map(|runtime| runtime.file)
+            .collect();
+        let runtime_file = runtime_files[0].clone();
+
+        // A manifest that leaves out a checksum is rejected as a whole
+        for file in server
+            .builds
+            .iter()
+            .map(|(_, build)| &build.file)
+            .chain(&runtime_files)
+        {
+            require_sha256(file, checksums.get(file))?;
+        }
+
+        let swfs_valid = server.builds.iter().all(|(build_name, build)| {
+            let file_path = path_string(&self.paths.swf(build_name, build.version_or(version)));
+            is_valid(&file_path, checksums.get(&build.file))
+        });
+
+        let refresh_builds = match self.local {
+            Some(local) => local.current_game_version != *version || !swfs_valid,
+            None => true,
+        };
+
+        let mut downloads = Vec::new();
+        if refresh_builds {
+            downloads.extend(swf_download_jobs(
+                self.paths,
+                &server.builds,
+                version,
+                checksums,
+            ));
+        }
+
+        let mut download_runtime = false;
+        for file in &runtime_files {
+            let runtime_path = path_string(&self.paths.runtime(file));
+            if self.local.is_none() || !is_valid(&runtime_path, checksums.get(file)) {
+                download_runtime |= *file == runtime_file;
+                downloads.push(runtime_download_job(self.paths, file, checksums));
+            }
+        }
+
+        let remove = if refresh_builds {
+            stale_versions(self.installed, &server.builds, version, self.keep_previous)
+        } else {
+            Vec::new()
+        };
+
+        Ok(UpdatePlan {
+            downloads,
+            refresh_builds,
+            runtime_file,
+            download_runtime,
+            runtime_files,
+            remove,
+            use_https: server.https_worked,
+            local_manifest: LocalVersionManifest {
+                current_game_version: server.current_game_version.clone(),
+                current_launcher_version: server.current_launcher_version.clone(),
+                builds: server.builds.clone(),
+                flash_runtimes: server.flash_runtimes.clone(),
+                checksums: server.checksums.clone(),
+                channel: server.channel.clone(),
+                https_worked: server.https_worked,
+            },
+        })
+    }
+}
+
+// Carries out a plan, reporting progress through the launcher's events
+pub async fn execute(launcher: &Launcher, plan: UpdatePlan) -> Result<(), LauncherError> {
+    if plan.refresh_builds {
+        launcher.info("Downloading latest SWFs");
+    }
+    if plan.download_runtime {
+        launcher.info("Downloading flash player for your platform...");
+    }
+
+    let download_results = download_all(
+        launcher,
+        plan.downloads,
+        plan.use_https,
+        launcher.settings.max_concurrent_downloads,
+    )
+    .await;
+
+    for result in &download_results {
+        if let Some(err) = &result.error {
+            launcher.info(format!("Could not download {}: {}", result.file_name, err));
+        }
+    }
+    launcher.emit(LauncherEvent::DownloadsFinished(download_results.clone()));
+
+    // Without a runtime there is nothing to launch the game with
+    if let Some(err) = download_results
+        .iter()
+        .find(|result| result.file_name == plan.runtime_file)
+        .and_then(|result| result.error.clone())
+    {
+        return Err(err);
+    }
+
+    // Only clean up once the new SWFs are all on disk, otherwise the
+    // previous version may be the only one that can still be played
+    let swfs_failed = download_results
+        .iter()
+        .any(|result| !plan.runtime_files.contains(&result.file_name) && result.error.is_some());
+
+    if plan.refresh_builds && !swfs_failed {
+        let pruned = remove_versions(&launcher.paths, &plan.remove);
+        launcher.emit(LauncherEvent::VersionsPruned(pruned));
+    }
+
+    match set_local_versions(&launcher.paths, plan.local_manifest).await {
+        Ok(()) => println!("Local version manifest successfully written to file."),
+        Err(err) => println!("Version manifest error: {}", err),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+    use std::path::Path;
+
+    fn server_manifest(version: &str) -> VersionManifest {
+        let files = [
+            "bymr-stable.swf",
+            "bymr-event.swf",
+            "flashplayer.exe",
+            "flashplayer.dmg",
+            "flashplayer",
+            "ruffle",
+        ];
+        let checksums: HashMap<&str, FileIntegrity> = files
+            .iter()
+            .map(|file| {
+                let integrity = FileIntegrity {
+                    sha256: Some("00".repeat(32)),
+                    size: None,
+                };
+                (*file, integrity)
+            })
+            .collect();
+
+        serde_json::from_value(serde_json::json!({
+            "currentGameVersion": version,
+            "currentLauncherVersion": "0.1.2",
+            "builds": {
+                "stable": "bymr-stable.swf",
+                "event": { "file": "bymr-event.swf", "version": "0.9.0" }
+            },
+            "flashRuntimes": {
+                "windows": "flashplayer.exe",
+                "darwin": "flashplayer.dmg",
+                "linux": "flashplayer"
+            },
+            "checksums": checksums,
+            "httpsWorked": true
+        }))
+        .unwrap()
+    }
+
+    fn local_manifest(version: &str) -> LocalVersionManifest {
+        LocalVersionManifest {
+            current_game_version: version.to_string(),
+            ..LocalVersionManifest::default()
+        }
+    }
+
+    fn installed(versions: &[&str]) -> Vec<InstalledVersion> {
+        versions
+            .iter()
+            .map(|version| InstalledVersion {
+                version: version.to_string(),
+                builds: vec!["stable".to_string()],
+                current: false,
+            })
+            .collect()
+    }
+
+    // Pretends exactly the given files are on disk and valid
+    fn valid_files(paths: &[std::path::PathBuf]) -> impl Fn(&str, Option<&FileIntegrity>) -> bool {
+        let valid: HashSet<String> = paths.iter().map(|path| path_string(path)).collect();
+        move |file_path, _| valid.contains(file_path)
+    }
+
+    fn download_names(plan: &UpdatePlan) -> Vec<&str> {
+        plan.downloads.iter().map(|job| job.url.as_str()).collect()
+    }
+
+    #[test]
+    fn fresh_install_downloads_everything() {
+        let paths = LauncherPaths::new(Path::new("data"));
+        let server = server_manifest("1.0.0");
+
+        let plan = Updater {
+            paths: &paths,
+            server: &server,
+            local: None,
+            installed: &[],
+            keep_previous: 2,
+        }
+        .plan("linux", valid_files(&[]))
+        .unwrap();
+
+        assert!(plan.refresh_builds);
+        assert!(plan.download_runtime);
+        assert_eq!(
+            download_names(&plan),
+            vec!["bymr-stable.swf", "bymr-event.swf", "flashplayer"]
+        );
+        assert_eq!(
+            plan.downloads[1].file_path,
+            path_string(&paths.swf("event", "0.9.0"))
+        );
+        assert!(plan.use_https);
+        assert_eq!(plan.local_manifest.current_game_version, "1.0.0");
+    }
+
+    #[test]
+    fn up_to_date_install_downloads_nothing() {
+        let paths = LauncherPaths::new(Path::new("data"));
+        let server = server_manifest("1.0.0");
+        let local = local_manifest("1.0.0");
+        let installed = installed(&["1.0.0", "0.9.0", "0.8.0"]);
+
+        let plan = Updater {
+            paths: &paths,
+            server: &server,
+            local: Some(&local),
+            installed: &installed,
+            keep_previous: 0,
+        }
+        .plan(
+            "linux",
+            valid_files(&[
+                paths.swf("stable", "1.0.0"),
+                paths.swf("event", "0.9.0"),
+                paths.runtime("flashplayer"),
+            ]),
+        )
+        .unwrap();
+
+        assert!(plan.downloads.is_empty());
+        assert!(!plan.refresh_builds);
+        // Nothing is pruned unless a new version was downloaded
+        assert!(plan.remove.is_empty());
+    }
+
+    #[test]
+    fn version_bump_replaces_builds_and_prunes_old_versions() {
+        let paths = LauncherPaths::new(Path::new("data"));
+        let server = server_manifest("1.1.0");
+        let local = local_manifest("1.0.0");
+        let installed = installed(&["1.0.0", "0.9.0", "0.8.0", "0.7.0"]);
+
+        let plan = Updater {
+            paths: &paths,
+            server: &server,
+            local: Some(&local),
+            installed: &installed,
+            keep_previous: 1,
+        }
+        .plan("windows", valid_files(&[paths.runtime("flashplayer.exe")]))
+        .unwrap();
+
+        assert_eq!(
+            download_names(&plan),
+            vec!["bymr-stable.swf", "bymr-event.swf"]
+        );
+        assert!(!plan.download_runtime);
+
+        // 0.9.0 is still used by the event build
+        let removed: Vec<_> = plan.remove.iter().map(|v| v.version.as_str()).collect();
+        assert_eq!(removed, vec!["0.8.0", "0.7.0"]);
+    }
+
+    #[test]
+    fn corrupt_files_are_downloaded_again() {
+        let paths = LauncherPaths::new(Path::new("data"));
+        let server = server_manifest("1.0.0");
+        let local = local_manifest("1.0.0");
+
+        let plan = Updater {
+            paths: &paths,
+            server: &server,
+            local: Some(&local),
+            installed: &installed(&["1.0.0"]),
+            keep_previous: 2,
+        }
+        .plan("darwin", valid_files(&[paths.swf("stable", "1.0.0")]))
+        .unwrap();
+
+        assert!(plan.refresh_builds);
+        assert!(plan.download_runtime);
+        assert_eq!(
+            download_names(&plan),
+            vec!["bymr-stable.swf", "bymr-event.swf", "flashplayer.dmg"]
+        );
+    }
+
+    #[test]
+    fn downloads_every_runtime_of_the_platform() {
+        let paths = LauncherPaths::new(Path::new("data"));
+        let mut server = server_manifest("1.0.0");
+        server.flash_runtimes = serde_json::from_value(serde_json::json!({
+            "windows": "flashplayer.exe",
+            "darwin": "flashplayer.dmg",
+            "linux": [
+                { "kind": "flash", "file": "flashplayer" },
+                { "kind": "ruffle", "file": "ruffle" }
+            ]
+        }))
+        .unwrap();
+        let local = local_manifest("1.0.0");
+
+        let plan = Updater {
+            paths: &paths,
+            server: &server,
+            local: Some(&local),
+            installed: &installed(&["1.0.0"]),
+            keep_previous: 2,
+        }
+        .plan(
+            "linux",
+            valid_files(&[
+                paths.swf("stable", "1.0.0"),
+                paths.swf("event", "0.9.0"),
+                paths.runtime("flashplayer"),
+            ]),
+        )
+        .unwrap();
+
+        // Only the default runtime is required to launch
+        assert!(!plan.download_runtime);
+        assert_eq!(download_names(&plan), vec!["ruffle"]);
+        assert_eq!(plan.runtime_file, "flashplayer");
+        assert_eq!(plan.runtime_files, vec!["flashplayer", "ruffle"]);
+    }
+
+    #[test]
+    fn manifest_without_checksum_has_no_plan() {
+        let paths = LauncherPaths::new(Path::new("data"));
+        let mut server = server_manifest("1.0.0");
+        server.checksums.remove("bymr-event.swf");
+
+        let result = Updater {
+            paths: &paths,
+            server: &server,
+            local: None,
+            installed: &[],
+            keep_previous: 2,
+        }
+        .plan("linux", valid_files(&[]));
+
+        assert_eq!(
+            result.unwrap_err(),
+            LauncherError::Integrity("The manifest has no sha256 for bymr-event.swf".to_string())
+        );
+    }
+
+    #[test]
+    fn unsupported_platform_has_no_plan() {
+        let paths = LauncherPaths::new(Path::new("data"));
+        let server = server_manifest("1.0.0");
+
+        let result = Updater {
+            paths: &paths,
+            server: &server,
+            local: None,
+            installed: &[],
+            keep_previous: 2,
+        }
+        .plan("freebsd", valid_files(&[]));
+
+        assert_eq!(
+            result.unwrap_err(),
+            LauncherError::UnsupportedPlatform("freebsd".to_string())
+        );
+    }
+}
@@ -1,24 +1,44 @@
 use crate::{
-    emit_event,
-    file_manager::{download_file, ensure_folder_exists, file_exists, get_local_versions},
+    error::LauncherError,
+    file_manager::{
+        download_file, ensure_folder_exists, get_local_versions, remove_stale_temp_files,
+    },
+    http_client::{MANIFEST_DEADLINE, REQUEST_TIMEOUT},
+    integrity::FileIntegrity,
+    launcher::Launcher,
+    paths::{path_string, LauncherPaths},
+    retry::{AttemptError, RetryNotice, RetryPolicy},
+    signature::verify_manifest_signature,
 };
-use reqwest;
+use indexmap::IndexMap;
+use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::{error::Error, path::Path};
-use tauri::AppHandle;
+use std::{cmp::Ordering, collections::HashMap, fs, sync::Arc};
+use tokio::{sync::Semaphore, task::JoinSet};
 
 pub const VERSION_INFO_PATH_BASE: &str = "api.bymrefitted.com/launcher.json";
 pub const DOWNLOAD_BASE_PATH: &str = "api.bymrefitted.com/launcher/downloads/";
 pub const DOWNLOADS_FOLDER: &str = "bymr-downloads";
-pub const BUILD_FOLDER: &str = "bymr-downloads/swfs";
-pub const RUNTIME_FOLDER: &str = "bymr-downloads/runtimes";
+// Relative to DOWNLOADS_FOLDER
+pub const BUILD_FOLDER: &str = "swfs";
+pub const RUNTIME_FOLDER: &str = "runtimes";
+pub const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 3;
+pub const DEFAULT_KEEP_PREVIOUS_VERSIONS: usize = 2;
+pub const DEFAULT_CHANNEL: &str = "stable";
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct LocalVersionManifest {
     pub current_game_version: String,
     pub current_launcher_version: String,
     pub builds: Builds,
     pub flash_runtimes: FlashRuntimes,
+    #[serde(default)]
+    pub checksums: HashMap<String, FileIntegrity>,
+    #[serde(default = "default_channel")]
+    pub channel: String,
+    // Whether the files were downloaded over HTTPS, so a repair uses the same
+    #[serde(default)]
+    pub https_worked: bool,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
@@ -30,127 +50,927 @@ pub struct VersionManifest {
     pub builds: Builds,
     #[serde(rename = "flashRuntimes")]
     pub flash_runtimes: FlashRuntimes,
+    // Optional integrity info keyed by the file names used in `builds` and `flashRuntimes`
+    #[serde(default)]
+    pub checksums: HashMap<String, FileIntegrity>,
     #[serde(rename = "httpsWorked")]
     pub https_worked: bool,
+    // Manifests without channels only have the top level version and builds,
+    // which is what the default channel uses
+    #[serde(default)]
+    pub channels: IndexMap<String, Channel>,
+    // The channel `current_game_version` and `builds` were taken from
+    #[serde(default = "default_channel")]
+    pub channel: String,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
-pub struct Builds {
-    stable: String,
-    http: String,
-    local: String,
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Channel {
+    pub version: String,
+    // Channels without their own builds use the top level ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub builds: Option<Builds>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changelog: Option<String>,
+}
+
+fn default_channel() -> String {
+    DEFAULT_CHANNEL.to_string()
+}
+
+impl VersionManifest {
+    // Points `current_game_version` and `builds` at the given channel
+    pub fn select_channel(&mut self, channel: &str) -> Result<(), LauncherError> {
+        let selected = match self.channels.get(channel) {
+            Some(selected) => selected.clone(),
+            None if channel == DEFAULT_CHANNEL => {
+                self.channel = default_channel();
+                return Ok(());
+            }
+            None => return Err(LauncherError::UnknownChannel(channel.to_string())),
+        };
+
+        self.current_game_version = selected.version;
+        if let Some(builds) = selected.builds {
+            self.builds = builds;
+        }
+        self.channel = channel.to_string();
+        Ok(())
+    }
+}
+
+// Used when the server can't be reached, so the last downloaded version can still be played
+impl From<LocalVersionManifest> for VersionManifest {
+    fn from(local: LocalVersionManifest) -> Self {
+        VersionManifest {
+            current_game_version: local.current_game_version,
+            current_launcher_version: local.current_launcher_version,
+            builds: local.builds,
+            flash_runtimes: local.flash_runtimes,
+            checksums: local.checksums,
+            https_worked: local.https_worked,
+            channels: IndexMap::new(),
+            channel: local.channel,
+        }
+    }
+}
+
+// Named builds in the order the server lists them. Build names end up in
+// SWF file names (`bymr-{name}-{version}.swf`), so builds with names that
+// contain dashes or path separators are skipped, leaving the others playable.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(from = "IndexMap<String, Build>")]
+pub struct Builds(IndexMap<String, Build>);
+
+impl From<IndexMap<String, Build>> for Builds {
+    fn from(mut builds: IndexMap<String, Build>) -> Self {
+        builds.retain(|name, _| {
+            let valid = !name.is_empty() && !name.contains(['-', '/', '\\']);
+            if !valid {
+                eprintln!("Skipping build with invalid name \"{}\"", name);
+            }
+            valid
+        });
+        Builds(builds)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(from = "BuildEntry", rename_all = "camelCase")]
+pub struct Build {
+    pub file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    // Builds can be pinned to a version other than `currentGameVersion`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+// A build is either just its file name, like older manifests have it, or an
+// object with extra metadata
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BuildEntry {
+    File(String),
+    #[serde(rename_all = "camelCase")]
+    Details {
+        file: String,
+        #[serde(default)]
+        display_name: Option<String>,
+        #[serde(default)]
+        description: Option<String>,
+        #[serde(default)]
+        version: Option<String>,
+    },
 }
 
+impl From<BuildEntry> for Build {
+    fn from(entry: BuildEntry) -> Self {
+        match entry {
+            BuildEntry::File(file) => Build {
+                file,
+                ..Build::default()
+            },
+            BuildEntry::Details {
+                file,
+                display_name,
+                description,
+                version,
+            } => Build {
+                file,
+                display_name,
+                description,
+                version,
+            },
+        }
+    }
+}
+
+impl Build {
+    pub fn version_or<'a>(&'a self, current_version: &'a str) -> &'a str {
+        self.version.as_deref().unwrap_or(current_version)
+    }
+}
+
+impl Builds {
+    pub fn get(&self, build_name: &str) -> Option<&Build> {
+        self.0.get(build_name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Build)> {
+        self.0.iter().map(|(name, build)| (name.as_str(), build))
+    }
+}
+
+// The runtimes each platform can play the game with, the first one is the default
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct FlashRuntimes {
-    windows: String,
-    darwin: String,
-    linux: String,
+    windows: PlatformRuntimes,
+    darwin: PlatformRuntimes,
+    linux: PlatformRuntimes,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(from = "RuntimeEntries")]
+pub struct PlatformRuntimes(Vec<Runtime>);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuntimeKind {
+    // The standalone Flash Player projector
+    Flash,
+    // The Ruffle desktop emulator
+    Ruffle,
+}
+
+impl RuntimeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RuntimeKind::Flash => "flash",
+            RuntimeKind::Ruffle => "ruffle",
+        }
+    }
+}
+
+impl Default for RuntimeKind {
+    fn default() -> Self {
+        RuntimeKind::Flash
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Runtime {
+    #[serde(default)]
+    pub kind: RuntimeKind,
+    pub file: String,
+    // Passed before anything the user adds, e.g. Ruffle's `--no-gui`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+}
+
+// Older manifests have a single Flash Player file name per platform
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RuntimeEntries {
+    File(String),
+    List(Vec<Runtime>),
+}
+
+impl From<RuntimeEntries> for PlatformRuntimes {
+    fn from(entries: RuntimeEntries) -> Self {
+        match entries {
+            RuntimeEntries::File(file) => PlatformRuntimes(vec![Runtime {
+                file,
+                ..Runtime::default()
+            }]),
+            RuntimeEntries::List(runtimes) => PlatformRuntimes(runtimes),
+        }
+    }
+}
+
+pub async fn get_version_info(launcher: &Launcher) -> Result<VersionManifest, LauncherError> {
+    match tokio::time::timeout(MANIFEST_DEADLINE, fetch_version_info(launcher)).await {
+        Ok(result) => result,
+        Err(_) => {
+            let msg = format!(
+                "Launcher manifest did not arrive within {}s",
+                MANIFEST_DEADLINE.as_secs()
+            );
+            launcher.info(msg.clone());
+            Err(LauncherError::Network(msg))
+        }
+    }
 }
 
-pub async fn get_version_info(app: &AppHandle) -> Result<VersionManifest, String> {
+async fn fetch_version_info(launcher: &Launcher) -> Result<VersionManifest, LauncherError> {
+    let client = &launcher.client;
+    let settings = &launcher.settings;
+    let policy = &launcher.retry;
+    let report_retry = |notice: &RetryNotice| launcher.info(notice.to_string());
     let mut https_worked = false;
 
-    
-    // First we try https
-    let resp = match reqwest::get(&format!("https://{}", VERSION_INFO_PATH_BASE)).await {
-        Ok(resp) => {
-            let connected_msg = "Launcher successfully connected over https".to_string();
-            emit_event(app, connected_msg);
-            https_worked = true;
-            resp
+    // With http to fall back to, https gets a single attempt and the retries
+    // are left for http
+    let https_policy = if settings.manifest_has_http_fallback() {
+        RetryPolicy {
+            max_attempts: 1,
+            ..policy.clone()
+        }
+    } else {
+        policy.clone()
+    };
+
+    // First we try https, unless the configured endpoint asks for a specific scheme
+    let https_url = settings.manifest_url(true);
+    let body = match https_policy
+        .run(
+            "Launcher manifest",
+            || fetch_text(client, &https_url),
+            report_retry,
+        )
+        .await
+    {
+        Ok(body) => {
+            https_worked = https_url.starts_with("https://");
+            let scheme = if https_worked { "https" } else { "http" };
+            let connected_msg = format!("Launcher successfully connected over {}", scheme);
+            launcher.info(connected_msg);
+            body
+        }
+        Err(err) if !settings.manifest_has_http_fallback() => {
+            let failed_msg = format!("Could not access {}: {}", https_url, err);
+            launcher.info(failed_msg);
+
+            return Err(err);
         }
         Err(err) => {
             // try via http if that fails
             let http_msg = format!("Could not access over https, attempting http: {}", err);
-            emit_event(app, http_msg);
+            launcher.info(http_msg);
 
-            match reqwest::get(&format!("http://{}", VERSION_INFO_PATH_BASE)).await {
-                Ok(resp) => resp,
+            let http_url = settings.manifest_url(false);
+            match policy
+                .run(
+                    "Launcher manifest",
+                    || fetch_text(client, &http_url),
+                    report_retry,
+                )
+                .await
+            {
+                Ok(body) => body,
                 Err(err) => {
                     let failed_http_msg = format!("Could not access over http, please check the server status on our discord: {}", err);
-                    emit_event(app, failed_http_msg);
+                    launcher.info(failed_http_msg);
 
-                    return Err(format!("Error code: {:?}, cause: {:?}", err.status(), err.source()));
+                    return Err(err);
                 }
             }
         }
     };
-    
-    if !resp.status().is_success() {
-        return Err(format!("Error code: {:?}", resp.status()));
+
+    // Never act on a manifest we didn't sign, no matter which scheme it came over
+    let signature_url = format!("{}.sig", settings.manifest_url(https_worked));
+    let signature = policy
+        .run(
+            "Manifest signature",
+            || fetch_text(client, &signature_url),
+            report_retry,
+        )
+        .await?;
+
+    if let Err(err) =
+        verify_manifest_signature(body.as_bytes(), &signature, &settings.manifest_public_key)
+    {
+        launcher.info(format!("Launcher manifest could not be verified: {}", err));
+        return Err(err);
     }
 
-    let body = resp.text().await.map_err(|err| err.to_string())?;
-    // if body.
     let mut data: VersionManifest = serde_json::from_str(&body).map_err(|err| {
         eprintln!("Error parsing JSON: {}", err);
-        err.to_string()
+        LauncherError::Decode(format!("Invalid launcher manifest: {}", err))
     })?;
 
     data.https_worked = https_worked;
     Ok(data)
 }
 
-pub fn local_files_status() -> (bool, LocalVersionManifest, String) {
-    let _ = ensure_folder_exists(DOWNLOADS_FOLDER);
-    let _ = ensure_folder_exists(BUILD_FOLDER);
-    let _ = ensure_folder_exists(RUNTIME_FOLDER);
+async fn fetch_text(client: &Client, url: &str) -> Result<String, AttemptError> {
+    let resp = match client.get(url).timeout(REQUEST_TIMEOUT).send().await {
+        Ok(resp) => resp,
+        Err(err) => {
+            return Err(AttemptError::transient(LauncherError::Network(
+                err.to_string(),
+            )))
+        }
+    };
 
-    return get_local_versions();
+    if !resp.status().is_success() {
+        return Err(AttemptError::from_status(
+            resp.status(),
+            resp.headers(),
+            url,
+        ));
+    }
+
+    resp.text()
+        .await
+        .map_err(|err| AttemptError::transient(LauncherError::Network(err.to_string())))
 }
 
-pub async fn download_swfs(builds: &Builds, version: &str, use_https: bool) -> Result<(), String> {
-    let builds_to_check = [
-        (&builds.stable, "stable"),
-        (&builds.http, "http"),
-        (&builds.local, "local"),
-    ];
+pub fn local_files_status(paths: &LauncherPaths) -> Result<LocalVersionManifest, LauncherError> {
+    ensure_folder_exists(&paths.downloads())?;
+    ensure_folder_exists(&paths.builds())?;
+    ensure_folder_exists(&paths.runtimes())?;
+    remove_stale_temp_files(&paths.downloads());
 
-    for (build_url, build_name) in &builds_to_check {
-        let build_path = format!("{}/bymr-{}-{}.swf", BUILD_FOLDER, build_name, version);
-        if let Err(err) = download_file(&build_path, build_url, use_https).await {
-            return Err(err);
+    get_local_versions(paths)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadJob {
+    pub file_path: String,
+    pub url: String,
+    pub integrity: Option<FileIntegrity>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadResult {
+    pub file_name: String,
+    pub error: Option<LauncherError>,
+}
+
+pub fn swf_download_jobs(
+    paths: &LauncherPaths,
+    builds: &Builds,
+    version: &str,
+    checksums: &HashMap<String, FileIntegrity>,
+) -> Vec<DownloadJob> {
+    builds
+        .iter()
+        .map(|(build_name, build)| DownloadJob {
+            file_path: path_string(&paths.swf(build_name, build.version_or(version))),
+            url: build.file.clone(),
+            integrity: checksums.get(&build.file).cloned(),
+        })
+        .collect()
+}
+
+pub fn runtime_download_job(
+    paths: &LauncherPaths,
+    flash_runtime_file_name: &str,
+    checksums: &HashMap<String, FileIntegrity>,
+) -> DownloadJob {
+    DownloadJob {
+        file_path: path_string(&paths.runtime(flash_runtime_file_name)),
+        url: flash_runtime_file_name.to_string(),
+        integrity: checksums.get(flash_runtime_file_name).cloned(),
+    }
+}
+
+// Runs all downloads concurrently, at most `max_concurrent` at a time. A
+// failing download doesn't stop the others, every job gets its own result
+// in the order the jobs were given.
+pub async fn download_all(
+    launcher: &Launcher,
+    jobs: Vec<DownloadJob>,
+    use_https: bool,
+    max_concurrent: usize,
+) -> Vec<DownloadResult> {
+    let semaphore = Arc::new(Semaphore::new(max_concurrent.max(1)));
+    let mut tasks = JoinSet::new();
+
+    let mut results: Vec<DownloadResult> = jobs
+        .iter()
+        .map(|job| DownloadResult {
+            file_name: job.url.clone(),
+            error: Some(LauncherError::Io(format!(
+                "Download of {} stopped unexpectedly",
+                job.url
+            ))),
+        })
+        .collect();
+
+    for (index, job) in jobs.into_iter().enumerate() {
+        let launcher = launcher.clone();
+        let semaphore = semaphore.clone();
+
+        tasks.spawn(async move {
+            // The semaphore is never closed, so this only ever waits for a free slot
+            let _permit = semaphore.acquire_owned().await;
+            let result = download_file(
+                &launcher,
+                &job.file_path,
+                &job.url,
+                use_https,
+                job.integrity.as_ref(),
+            )
+            .await;
+
+            (index, result)
+        });
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((index, result)) = joined {
+            results[index].error = result.err();
+        }
+    }
+
+    results
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstalledVersion {
+    pub version: String,
+    pub builds: Vec<String>,
+    pub current: bool,
+}
+
+// Every version with at least one SWF in the builds folder, newest first
+pub fn installed_versions(paths: &LauncherPaths, current_version: &str) -> Vec<InstalledVersion> {
+    let entries = match fs::read_dir(paths.builds()) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut versions: Vec<InstalledVersion> = Vec::new();
+
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let (build_name, version) = match parse_swf_file_name(&file_name.to_string_lossy()) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+
+        match versions
+            .iter_mut()
+            .find(|installed| installed.version == version)
+        {
+            Some(installed) => installed.builds.push(build_name),
+            None => versions.push(InstalledVersion {
+                current: version == current_version,
+                version,
+                builds: vec![build_name],
+            }),
         }
     }
 
-    Ok(())
+    for installed in &mut versions {
+        installed.builds.sort();
+    }
+    versions.sort_by(|a, b| compare_versions(&b.version, &a.version));
+    versions
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PruneResult {
+    pub removed_versions: Vec<String>,
+    pub bytes_freed: u64,
+}
+
+// Every installed version except the ones the manifest uses and the
+// `keep_previous` newest of the others
+pub fn stale_versions(
+    installed: &[InstalledVersion],
+    builds: &Builds,
+    current_version: &str,
+    keep_previous: usize,
+) -> Vec<InstalledVersion> {
+    let in_use: Vec<&str> = builds
+        .iter()
+        .map(|(_, build)| build.version_or(current_version))
+        .chain([current_version])
+        .collect();
+
+    installed
+        .iter()
+        .filter(|installed| !in_use.contains(&installed.version.as_str()))
+        .skip(keep_previous)
+        .cloned()
+        .collect()
 }
 
-pub fn do_all_swfs_exist(builds: &Builds, version: &str) -> bool {
-    let builds_to_check = [
-        (&builds.stable, "stable"),
-        (&builds.http, "http"),
-        (&builds.local, "local"),
-    ];
+// Deletes the SWFs of the given versions
+pub fn remove_versions(paths: &LauncherPaths, versions: &[InstalledVersion]) -> PruneResult {
+    let mut result = PruneResult::default();
 
-    for (_, build_name) in &builds_to_check {
-        let binding = Path::new(BUILD_FOLDER).join(format!("bymr-{}-{}.swf", build_name, version));
-        let file_path = binding.to_str().unwrap();
+    for installed in versions {
+        for build_name in &installed.builds {
+            let swf_path = paths.swf(build_name, &installed.version);
+            let size = fs::metadata(&swf_path).map(|meta| meta.len()).unwrap_or(0);
 
-        if !file_exists(file_path) {
-            return false;
+            match fs::remove_file(&swf_path) {
+                Ok(()) => result.bytes_freed += size,
+                Err(err) => eprintln!("Failed to remove {}: {}", swf_path.display(), err),
+            }
         }
+        result.removed_versions.push(installed.version.clone());
     }
-    true
+
+    result
 }
 
-pub async fn download_runtimes(
-    flash_runtime_file_name: &str,
-    use_https: bool,
-) -> Result<(), String> {
-    let flash_file_path = format!("{}/{}", RUNTIME_FOLDER, flash_runtime_file_name);
-    download_file(&flash_file_path, flash_runtime_file_name, use_https).await
+// The inverse of `LauncherPaths::swf`, `bymr-{build}-{version}.swf`. `Builds`
+// rejects names with dashes, so the first one ends the build name.
+fn parse_swf_file_name(file_name: &str) -> Option<(String, String)> {
+    let stem = file_name.strip_prefix("bymr-")?.strip_suffix(".swf")?;
+    let (build_name, version) = stem.split_once('-')?;
+
+    if build_name.is_empty() || version.is_empty() {
+        return None;
+    }
+    Some((build_name.to_string(), version.to_string()))
 }
 
+// Compares dotted versions numerically where possible, so 1.10.0 sorts after
+// 1.9.0. Like semver, a pre-release such as 1.1.0-beta sorts before 1.1.0.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a_release, a_pre) = split_pre_release(a);
+    let (b_release, b_pre) = split_pre_release(b);
+
+    compare_parts(a_release, b_release).then_with(|| match (a_pre, b_pre) {
+        (None, None) => Ordering::Equal,
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(a_pre), Some(b_pre)) => compare_parts(a_pre, b_pre),
+    })
+}
+
+fn split_pre_release(version: &str) -> (&str, Option<&str>) {
+    match version.split_once('-') {
+        Some((release, pre)) => (release, Some(pre)),
+        None => (version, None),
+    }
+}
+
+fn compare_parts(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split('.');
+    let mut b_parts = b.split('.');
+
+    loop {
+        let ordering = match (a_parts.next(), b_parts.next()) {
+            (None, None) => return Ordering::Equal,
+            (Some(_), None) => Ordering::Greater,
+            (None, Some(_)) => Ordering::Less,
+            (Some(a_part), Some(b_part)) => match (a_part.parse::<u64>(), b_part.parse::<u64>()) {
+                (Ok(a_number), Ok(b_number)) => a_number.cmp(&b_number),
+                _ => a_part.cmp(b_part),
+            },
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+pub fn get_platform_runtimes(
+    platform: &str,
+    server_manifest: &VersionManifest,
+) -> Result<Vec<Runtime>, LauncherError> {
+    let runtimes = match platform {
+        "windows" => &server_manifest.flash_runtimes.windows,
+        "darwin" => &server_manifest.flash_runtimes.darwin,
+        "linux" => &server_manifest.flash_runtimes.linux,
+        _ => return Err(LauncherError::UnsupportedPlatform(platform.to_string())),
+    };
+
+    if runtimes.0.is_empty() {
+        return Err(LauncherError::UnsupportedPlatform(platform.to_string()));
+    }
+    Ok(runtimes.0.clone())
+}
+
+// The file name of the platform's default runtime
 pub fn get_platform_flash_runtime(
     platform: &str,
     server_manifest: &VersionManifest,
-) -> Result<String, String> {
-    match platform {
-        "windows" => Ok(server_manifest.flash_runtimes.windows.clone()),
-        "darwin" => Ok(server_manifest.flash_runtimes.darwin.clone()),
-        "linux" => Ok(server_manifest.flash_runtimes.linux.clone()),
-        _ => Err(format!("unsupported platform: {}", platform)),
+) -> Result<String, LauncherError> {
+    let runtimes = get_platform_runtimes(platform, server_manifest)?;
+    Ok(runtimes[0].file.clone())
+}
+
+// Runtimes are picked by file name. Files the manifest doesn't list any more
+// are assumed to be a Flash Player.
+pub fn find_runtime(runtimes: &[Runtime], file: &str) -> Runtime {
+    runtimes
+        .iter()
+        .find(|runtime| runtime.file == file)
+        .cloned()
+        .unwrap_or_else(|| Runtime {
+            file: file.to_string(),
+            ..Runtime::default()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_release_channel() {
+        let mut manifest: VersionManifest = serde_json::from_str(
+            r#"{
+                "currentGameVersion": "1.0.0",
+                "currentLauncherVersion": "0.1.2",
+                "builds": {"stable": "bymr-stable.swf"},
+                "flashRuntimes": {"windows": "flash.exe", "darwin": "flash.dmg", "linux": "flash"},
+                "httpsWorked": false,
+                "channels": {
+                    "stable": {"version": "1.0.0"},
+                    "beta": {
+                        "version": "1.1.0-beta",
+                        "builds": {"beta": "bymr-beta.swf"},
+                        "changelog": "New buildings"
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.channel, DEFAULT_CHANNEL);
+
+        manifest.select_channel("beta").unwrap();
+        assert_eq!(manifest.channel, "beta");
+        assert_eq!(manifest.current_game_version, "1.1.0-beta");
+        assert!(manifest.builds.get("beta").is_some());
+
+        assert_eq!(
+            manifest.select_channel("nightly"),
+            Err(LauncherError::UnknownChannel("nightly".to_string()))
+        );
+        assert_eq!(manifest.channel, "beta");
+    }
+
+    #[test]
+    fn default_channel_works_without_channels() {
+        let mut manifest = VersionManifest {
+            current_game_version: "1.0.0".to_string(),
+            ..VersionManifest::default()
+        };
+
+        manifest.select_channel(DEFAULT_CHANNEL).unwrap();
+        assert_eq!(manifest.current_game_version, "1.0.0");
+        assert!(manifest.select_channel("beta").is_err());
+    }
+
+    #[test]
+    fn reads_single_and_listed_runtimes() {
+        let manifest: VersionManifest = serde_json::from_str(
+            r#"{
+                "currentGameVersion": "1.0.0",
+                "currentLauncherVersion": "0.1.2",
+                "builds": {"stable": "bymr-stable.swf"},
+                "flashRuntimes": {
+                    "windows": "flash.exe",
+                    "darwin": [],
+                    "linux": [
+                        {"kind": "flash", "file": "flashplayer"},
+                        {"kind": "ruffle", "file": "ruffle", "args": ["--no-gui"], "displayName": "Ruffle"}
+                    ]
+                },
+                "httpsWorked": false
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            get_platform_runtimes("windows", &manifest).unwrap(),
+            vec![Runtime {
+                kind: RuntimeKind::Flash,
+                file: "flash.exe".to_string(),
+                args: Vec::new(),
+                display_name: None,
+            }]
+        );
+
+        let linux = get_platform_runtimes("linux", &manifest).unwrap();
+        assert_eq!(linux[1].kind, RuntimeKind::Ruffle);
+        assert_eq!(linux[1].args, vec!["--no-gui"]);
+        assert_eq!(
+            get_platform_flash_runtime("linux", &manifest).unwrap(),
+            "flashplayer"
+        );
+        assert_eq!(find_runtime(&linux, "ruffle"), linux[1]);
+        assert_eq!(find_runtime(&linux, "old").kind, RuntimeKind::Flash);
+
+        assert!(matches!(
+            get_platform_runtimes("darwin", &manifest),
+            Err(LauncherError::UnsupportedPlatform(_))
+        ));
+
+        // Written back in the new format, which reads the same
+        let local: LocalVersionManifest = serde_json::from_str(
+            &serde_json::to_string(&LocalVersionManifest {
+                flash_runtimes: manifest.flash_runtimes.clone(),
+                ..LocalVersionManifest::default()
+            })
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            get_platform_runtimes("linux", &VersionManifest::from(local)).unwrap(),
+            linux
+        );
+    }
+
+    #[test]
+    fn parses_swf_file_names() {
+        assert_eq!(
+            parse_swf_file_name("bymr-stable-1.2.3.swf"),
+            Some(("stable".to_string(), "1.2.3".to_string()))
+        );
+        assert_eq!(
+            parse_swf_file_name("bymr-http-1.2.3-beta.swf"),
+            Some(("http".to_string(), "1.2.3-beta".to_string()))
+        );
+        assert_eq!(parse_swf_file_name("bymr-stable-1.2.3.swf.part"), None);
+        assert_eq!(parse_swf_file_name("flashplayer.exe"), None);
+    }
+
+    #[test]
+    fn compares_versions_numerically() {
+        assert_eq!(compare_versions("1.10.0", "1.9.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.2", "1.2.1"), Ordering::Less);
+        assert_eq!(compare_versions("2.0.0", "2.0.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.1.0-beta", "1.1.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.1.0-beta", "1.0.0"), Ordering::Greater);
+        assert_eq!(
+            compare_versions("1.1.0-beta.2", "1.1.0-beta.10"),
+            Ordering::Less
+        );
+    }
+
+    fn prune(
+        paths: &LauncherPaths,
+        builds: &Builds,
+        current_version: &str,
+        keep_previous: usize,
+    ) -> PruneResult {
+        let installed = installed_versions(paths, current_version);
+        let stale = stale_versions(&installed, builds, current_version, keep_previous);
+        remove_versions(paths, &stale)
+    }
+
+    #[test]
+    fn prunes_all_but_current_and_previous_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = LauncherPaths::new(dir.path());
+        fs::create_dir_all(paths.builds()).unwrap();
+
+        for version in ["1.0.0", "1.1.0", "1.2.0", "1.3.0"] {
+            fs::write(paths.swf("stable", version), b"swf").unwrap();
+            fs::write(paths.swf("local", version), b"local").unwrap();
+        }
+
+        let result = prune(&paths, &Builds::default(), "1.3.0", 1);
+
+        assert_eq!(result.removed_versions, vec!["1.1.0", "1.0.0"]);
+        assert_eq!(result.bytes_freed, 16);
+
+        let remaining: Vec<_> = installed_versions(&paths, "1.3.0")
+            .into_iter()
+            .map(|installed| installed.version)
+            .collect();
+        assert_eq!(remaining, vec!["1.3.0", "1.2.0"]);
+    }
+
+    #[test]
+    fn keeps_versions_pinned_by_builds() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = LauncherPaths::new(dir.path());
+        fs::create_dir_all(paths.builds()).unwrap();
+
+        fs::write(paths.swf("stable", "2.0.0"), b"swf").unwrap();
+        fs::write(paths.swf("stable", "1.0.0"), b"swf").unwrap();
+        fs::write(paths.swf("event", "0.9.0"), b"swf").unwrap();
+
+        let builds: Builds = serde_json::from_str(
+            r#"{"stable": "stable.swf", "event": {"file": "event.swf", "version": "0.9.0"}}"#,
+        )
+        .unwrap();
+        let result = prune(&paths, &builds, "2.0.0", 0);
+
+        assert_eq!(result.removed_versions, vec!["1.0.0"]);
+        assert!(paths.swf("event", "0.9.0").exists());
+    }
+
+    #[test]
+    fn reads_plain_and_detailed_builds_in_order() {
+        let builds: Builds = serde_json::from_str(
+            r#"{
+                "stable": "bymr-stable.swf",
+                "beta": {"file": "bymr-beta.swf", "displayName": "Beta", "version": "1.1.0"},
+                "local": "bymr-local.swf"
+            }"#,
+        )
+        .unwrap();
+
+        let names: Vec<_> = builds.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["stable", "beta", "local"]);
+
+        let beta = builds.get("beta").unwrap();
+        assert_eq!(beta.display_name.as_deref(), Some("Beta"));
+        assert_eq!(beta.version_or("1.0.0"), "1.1.0");
+        assert_eq!(builds.get("local").unwrap().file, "bymr-local.swf");
+        assert_eq!(builds.get("stable").unwrap().version_or("1.0.0"), "1.0.0");
+
+        // Stored manifests round trip through the detailed form
+        let stored = serde_json::to_string(&builds).unwrap();
+        assert_eq!(serde_json::from_str::<Builds>(&stored).unwrap(), builds);
+    }
+
+    #[test]
+    fn skips_build_names_that_break_file_names() {
+        let builds: Builds = serde_json::from_str(
+            r#"{"stable": "bymr-stable.swf", "beta-event": "bymr-beta-event.swf", "../http": "x.swf", "a\\b": "y.swf", "": "z.swf"}"#,
+        )
+        .unwrap();
+
+        let names: Vec<_> = builds.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["stable"]);
+    }
+
+    #[test]
+    fn download_jobs_follow_build_versions() {
+        let paths = LauncherPaths::new("data");
+        let builds: Builds = serde_json::from_str(
+            r#"{"stable": "bymr-stable.swf", "beta": {"file": "bymr-beta.swf", "version": "1.1.0"}}"#,
+        )
+        .unwrap();
+
+        let jobs = swf_download_jobs(&paths, &builds, "1.0.0", &HashMap::new());
+
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(
+            jobs[0].file_path,
+            path_string(&paths.swf("stable", "1.0.0"))
+        );
+        assert_eq!(jobs[1].file_path, path_string(&paths.swf("beta", "1.1.0")));
+        assert_eq!(jobs[1].url, "bymr-beta.swf");
+    }
+
+    #[test]
+    fn lists_installed_versions_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = LauncherPaths::new(dir.path());
+        fs::create_dir_all(paths.builds()).unwrap();
+
+        for (build_name, version) in [
+            ("stable", "1.9.0"),
+            ("local", "1.10.0"),
+            ("stable", "1.10.0"),
+        ] {
+            fs::write(paths.swf(build_name, version), b"swf").unwrap();
+        }
+        fs::write(paths.builds().join("bymr-http-1.11.0.swf.part"), b"sw").unwrap();
+
+        let versions = installed_versions(&paths, "1.10.0");
+
+        assert_eq!(
+            versions,
+            vec![
+                InstalledVersion {
+                    version: "1.10.0".to_string(),
+                    builds: vec!["local".to_string(), "stable".to_string()],
+                    current: true,
+                },
+                InstalledVersion {
+                    version: "1.9.0".to_string(),
+                    builds: vec!["stable".to_string()],
+                    current: false,
+                },
+            ]
+        );
     }
 }
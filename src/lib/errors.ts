@@ -0,0 +1,37 @@
+// Mirrors `LauncherError` in src-tauri/src/error.rs
+export interface LauncherError {
+  code:
+    | "network"
+    | "httpStatus"
+    | "io"
+    | "decode"
+    | "missingFile"
+    | "unsupportedPlatform"
+    | "integrity"
+    | "unknownChannel"
+    | "invalidLaunchOptions";
+  message: string;
+}
+
+const guidance: Record<LauncherError["code"], string> = {
+  network: "Check your internet connection and try again.",
+  httpStatus: "The server had a problem, please try again later.",
+  io: "Make sure the launcher can write to its data folder and that your disk is not full.",
+  decode: "A launcher file is damaged, restarting the launcher will download it again.",
+  missingFile: "Restart the launcher to download the missing files.",
+  unsupportedPlatform: "There is no flash runtime available for your system.",
+  integrity: "A downloaded file is corrupt, restart the launcher to download it again.",
+  unknownChannel: "Pick a different release channel.",
+  invalidLaunchOptions: "Check the launch options of this build.",
+};
+
+const isLauncherError = (error: unknown): error is LauncherError =>
+  typeof error === "object" && error !== null && "code" in error && "message" in error;
+
+// Turns whatever a command rejected with into something to show the user
+export const describeError = (error: unknown): string => {
+  if (!isLauncherError(error)) return String(error);
+
+  const hint = guidance[error.code];
+  return hint ? `${error.message}. ${hint}` : error.message;
+};
@@ -0,0 +1,10 @@
+// Mirrors `GameExit` in src-tauri/src/supervisor.rs
+export interface GameExit {
+  buildName: string;
+  version: string;
+  runtime: string;
+  code: number | null;
+  signal: number | null;
+  durationSeconds: number;
+  crashed: boolean;
+}
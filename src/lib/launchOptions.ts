@@ -0,0 +1,30 @@
+// Mirrors `LaunchOptions` in src-tauri/src/launch_options.rs
+export interface LaunchOptions {
+  flashvars: Record<string, string>;
+  args: string[];
+  env: Record<string, string>;
+}
+
+// Options are edited as text, one `name=value` pair or argument per line
+export const parsePairs = (text: string): Record<string, string> => {
+  const pairs: Record<string, string> = {};
+  for (const line of text.split("\n")) {
+    if (!line.trim()) continue;
+
+    const separator = line.indexOf("=");
+    if (separator === -1) {
+      pairs[line.trim()] = "";
+    } else {
+      pairs[line.slice(0, separator).trim()] = line.slice(separator + 1);
+    }
+  }
+  return pairs;
+};
+
+export const formatPairs = (pairs: Record<string, string>): string =>
+  Object.entries(pairs)
+    .map(([name, value]) => `${name}=${value}`)
+    .join("\n");
+
+export const parseLines = (text: string): string[] =>
+  text.split("\n").map((line) => line.trim()).filter((line) => line);